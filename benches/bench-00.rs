@@ -1,6 +1,6 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use image::{self, DynamicImage};
-use pixlzr::{FilterType, Pixlzr};
+use pixlzr::{policy::PowerOfTwo, FilterType, Pixlzr};
 use std::{
 	fs::read as read_file,
 	io::{BufWriter, Cursor},
@@ -8,44 +8,38 @@ use std::{
 
 type V = Vec<u8>;
 
-fn decode_pix(data: V) -> () {
+fn decode_pix(data: V) {
 	let _ = Pixlzr::decode_from_vec(data).unwrap();
-	()
 }
-fn encode_pix(pix: &Pixlzr) -> () {
+fn encode_pix(pix: &Pixlzr) {
 	pix.encode_to_vec().unwrap();
-	()
 }
-fn decode_png(data: &V) -> () {
+fn decode_png(data: &V) {
 	image::load_from_memory_with_format(
 		data.as_slice(),
 		image::ImageFormat::Png,
 	)
 	.unwrap();
-	()
 }
-fn encode_png(img: &DynamicImage) -> () {
+fn encode_png(img: &DynamicImage) {
 	// img.write_with_encoder(image::codecs::png::PngEncoder)
 	let mut buff = BufWriter::new(Cursor::new(Vec::<u8>::new()));
 	img.write_to(&mut buff, image::ImageFormat::Png).unwrap();
-	()
 }
 
-fn convert_to_image(img: &DynamicImage) -> () {
+fn convert_to_image(img: &DynamicImage) {
 	let _ = Pixlzr::from_image(img, 64, 64);
-	()
 }
-fn shrink(pix: &mut Pixlzr) -> () {
-	pix.shrink_by(FilterType::CatmullRom, 0.25);
-	()
+fn shrink(pix: &mut Pixlzr) {
+	pix.shrink_by(FilterType::CatmullRom, 0.25, &PowerOfTwo);
 }
 
 pub fn criterion_benchmark(c: &mut Criterion) {
 	let path_pix: &str = "./benches/base.pixlzr";
 	let path_png: &str = "./benches/base.png";
 
-	let data_pix: V = read_file(&path_pix).unwrap();
-	let data_png: V = read_file(&path_png).unwrap();
+	let data_pix: V = read_file(path_pix).unwrap();
+	let data_png: V = read_file(path_png).unwrap();
 
 	let img = image::load_from_memory_with_format(
 		&data_png,
@@ -58,6 +52,18 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 	c.bench_function("raw decoding pix", |b| {
 		b.iter(|| decode_pix(black_box(data_pix.clone())))
 	});
+	// raw -> pix, in a single thread, to compare with the per-line
+	// parallel decoding above
+	let single_thread = rayon::ThreadPoolBuilder::new()
+		.num_threads(1)
+		.build()
+		.unwrap();
+	c.bench_function("raw decoding pix - single thread", |b| {
+		b.iter(|| {
+			single_thread
+				.install(|| decode_pix(black_box(data_pix.clone())))
+		})
+	});
 	// raw -> png
 	c.bench_function("raw decoding png", |b| {
 		b.iter(|| decode_png(black_box(&data_png)))
@@ -80,7 +86,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 		b.iter(|| shrink(black_box(&mut (pix.clone()))))
 	});
 
-	pix.shrink_by(FilterType::CatmullRom, 1.0);
+	pix.shrink_by(FilterType::CatmullRom, 1.0, &PowerOfTwo);
 	c.bench_function("raw encoding pix - shrunk", |b| {
 		b.iter(|| encode_pix(black_box(&pix)))
 	});
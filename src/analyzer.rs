@@ -0,0 +1,231 @@
+//! Measures of the detail of a block, which drive how much it's shrunk.
+
+use crate::{
+	data_types::PixlzrBlock,
+	operations::{
+		get_block_dct_energy, get_block_entropy, get_block_laplacian,
+		get_block_variance, get_block_variance_directionally,
+	},
+};
+
+/// The scale of `OklabDeviation`'s default mean absolute deviation
+const BASE_FACTOR: f32 = 10.0;
+
+/// Measures the detail of a block along each axis.
+///
+/// The scores are in `[0; 1]`, for blocks kept whole, and below for the
+/// ones to be shrunk: each halving of a score halves that side of the
+/// block. Closures of a block into a score are analyzers too.
+pub trait BlockAnalyzer: Sync {
+	/// The (horizontal, vertical) detail of the `block`
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32);
+}
+
+impl<F> BlockAnalyzer for F
+where
+	F: Fn(&PixlzrBlock) -> (f32, f32) + Sync,
+{
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32) {
+		self(block)
+	}
+}
+
+/// The deviation of the Oklab samples of a block from their average, as
+/// measured by `get_block_variance`, the same along both axes.
+///
+/// `before` takes each sample and the average, and `after` takes the mean
+/// of their results. By default, the mean absolute deviation is scaled
+/// as `Pixlzr::shrink_by` does.
+#[derive(Clone, Copy, Debug)]
+pub struct OklabDeviation {
+	pub before: fn(f32, f32) -> f32,
+	pub after: fn(f32) -> f32,
+}
+
+impl Default for OklabDeviation {
+	fn default() -> Self {
+		Self {
+			before: |x: f32, avg: f32| (x - avg).abs(),
+			after: |x: f32| x * BASE_FACTOR,
+		}
+	}
+}
+
+impl BlockAnalyzer for OklabDeviation {
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32) {
+		let value = get_block_variance(block, &self.before, &self.after);
+		(value, value)
+	}
+}
+
+/// The horizontal and vertical Sobel gradients of a block, as measured by
+/// `get_block_variance_directionally`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sobel;
+
+impl BlockAnalyzer for Sobel {
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32) {
+		get_block_variance_directionally(block)
+	}
+}
+
+/// The standard deviation of the Laplacian of the lightness of a block, as
+/// measured by `get_block_laplacian`, the same along both axes.
+///
+/// Rates smooth gradients as flat, and sharp edges and fine textures as
+/// detailed.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Laplacian;
+
+impl BlockAnalyzer for Laplacian {
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32) {
+		let value = get_block_laplacian(block);
+		(value, value)
+	}
+}
+
+/// The Shannon entropy of the lightness histogram of a block, as measured
+/// by `get_block_entropy`, the same along both axes.
+///
+/// Rates blocks of few tones as flat, wherever they are.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Entropy;
+
+impl BlockAnalyzer for Entropy {
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32) {
+		let value = get_block_entropy(block);
+		(value, value)
+	}
+}
+
+/// The share of the energy of the 8x8 DCT of the lightness of a block in
+/// its high frequencies, as measured by `get_block_dct_energy`, the same
+/// along both axes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DctEnergy;
+
+impl BlockAnalyzer for DctEnergy {
+	fn analyze(&self, block: &PixlzrBlock) -> (f32, f32) {
+		let value = get_block_dct_energy(block);
+		(value, value)
+	}
+}
+
+pub mod tests_on_analyzer {
+	#[allow(unused_imports)]
+	use super::{
+		BlockAnalyzer, DctEnergy, Entropy, Laplacian, OklabDeviation,
+		Sobel,
+	};
+	#[allow(unused_imports)]
+	use crate::data_types::test_images::noise;
+	#[allow(unused_imports)]
+	use crate::data_types::{FilterType, Pixlzr, PixlzrBlock};
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use image::{DynamicImage, RgbImage};
+
+	#[allow(dead_code)]
+	fn sample_pixlzr() -> Pixlzr {
+		let img = RgbImage::from_fn(48, 32, |x, y| {
+			let noise = noise(x, y, 97);
+			image::Rgb([(x * 4 + noise) as u8, (y * 6) as u8, 60])
+		});
+		Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16)
+	}
+
+	#[test]
+	fn test_builtin_analyzers() {
+		let mut by = sample_pixlzr();
+		by.shrink_by(FilterType::Nearest, 0.5, &PowerOfTwo);
+		let mut with = sample_pixlzr();
+		with.shrink_with(
+			FilterType::Nearest,
+			&OklabDeviation::default(),
+			0.5,
+			&PowerOfTwo,
+		);
+		assert!(by
+			.blocks
+			.iter()
+			.zip(with.blocks.iter())
+			.all(|(a, b)| a.block_value() == b.block_value()
+				&& a.as_slice() == b.as_slice()));
+
+		let mut by = sample_pixlzr();
+		by.shrink_directionally(FilterType::Nearest, 2., &PowerOfTwo);
+		let mut with = sample_pixlzr();
+		with.shrink_with(FilterType::Nearest, &Sobel, 2., &PowerOfTwo);
+		assert_eq!(by.block_scale_levels(), with.block_scale_levels());
+	}
+
+	#[test]
+	fn test_shrink_directionally_again() {
+		// Unlike `shrink_with`, shrinks the shrunk blocks again
+		let mut pix = sample_pixlzr();
+		pix.shrink_directionally(FilterType::Nearest, 4., &PowerOfTwo);
+		let once = pix.block_scale_levels();
+		let mut with = pix.clone();
+		with.shrink_with(FilterType::Nearest, &Sobel, 4., &PowerOfTwo);
+		assert_eq!(with.block_scale_levels(), once);
+
+		pix.shrink_directionally(FilterType::Nearest, 4., &PowerOfTwo);
+		let twice = pix.block_scale_levels();
+		assert!(once.iter().zip(twice.iter()).any(|(once, twice)| {
+			once.zip(*twice).is_some_and(|(once, twice)| {
+				twice.0 + twice.1 > once.0 + once.1
+			})
+		}));
+	}
+
+	#[test]
+	fn test_custom_analyzer() {
+		// Keeps the first column of blocks whole, and shrinks the others
+		// across
+		let analyzer = |block: &PixlzrBlock| {
+			let whole = block.as_slice()[0] < 16;
+			if whole {
+				(1., 1.)
+			} else {
+				(0.25, 1.)
+			}
+		};
+		let mut pix = sample_pixlzr();
+		pix.shrink_with(FilterType::Nearest, &analyzer, 1., &PowerOfTwo);
+		assert_eq!(pix.blocks[0].dimensions(), (16, 16));
+		assert_eq!(pix.blocks[2].dimensions(), (4, 16));
+		assert_eq!(pix.blocks[2].axis_values(), Some((0.25, 1.)));
+	}
+
+	#[test]
+	fn test_detail_metrics() {
+		let block = |f: fn(u32, u32) -> u8| -> PixlzrBlock {
+			let img = RgbImage::from_fn(16, 16, |x, y| {
+				let v = f(x, y);
+				image::Rgb([v, v, v])
+			});
+			Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16)
+				.blocks[0]
+				.clone()
+		};
+		let flat = block(|_, _| 120);
+		let gradient = block(|x, y| (x * 8 + y * 6) as u8);
+		let checkers =
+			block(|x, y| if (x + y) % 2 == 0 { 0 } else { 255 });
+
+		for analyzer in [&Laplacian as &dyn BlockAnalyzer, &DctEnergy] {
+			assert!(analyzer.analyze(&flat).0 < 1e-3);
+			let (gradient, checkers) = (
+				analyzer.analyze(&gradient).0,
+				analyzer.analyze(&checkers).0,
+			);
+			assert!(gradient < 0.1 && checkers > 0.9);
+		}
+
+		// Two tones are flat to the entropy, and a gradient is detailed
+		assert_eq!(Entropy.analyze(&flat).0, 0.);
+		assert!(Entropy.analyze(&checkers).0 < 0.2);
+		assert!(Entropy.analyze(&gradient).0 > 0.6);
+	}
+}
@@ -1,8 +1,30 @@
+use anyhow::bail;
 use anyhow::{Context, Result};
 use clap::Parser;
-use image::open;
-use pixlzr::{FilterType, Pixlzr};
-use std::path::{Path, PathBuf};
+use clap::ValueEnum;
+use image::{
+	codecs::{
+		gif::GifDecoder,
+		jpeg::JpegEncoder,
+		png::{PngDecoder, PngEncoder},
+		tiff::TiffEncoder,
+		webp::WebPEncoder,
+	},
+	imageops, open, AnimationDecoder, DynamicImage, GrayImage,
+	ImageEncoder, ImageFormat, ImageReader,
+};
+use pixlzr::{
+	alpha_mask,
+	analyzer::{DctEnergy, Entropy, Laplacian, OklabDeviation, Sobel},
+	policy::{Clamped, Continuous, PowerOfTwo},
+	BlockAnalyzer, Compression, FilterType, Metadata, Pixlzr,
+	PixlzrSequence, ShrinkPolicy, Target, PIXLZR_SEQUENCE_MAGIC_NUMBERS,
+};
+use std::{
+	fs::File,
+	io::{BufReader, BufWriter, Read},
+	path::{Path, PathBuf},
+};
 
 #[derive(Parser, Debug, Default)]
 #[command(author, version, about, long_about = None)]
@@ -37,9 +59,136 @@ pub struct CliArgs {
 	/// Direction-wise scan
 	#[arg(short, long)]
 	direction_wise: Option<bool>,
+	/// The measure of the detail of each block.
+	///  Defaults to `deviation`, or to `sobel` if direction-wise
+	#[arg(short, long, value_enum)]
+	metric: Option<Metric>,
+	/// How the detail of each block maps into its scale.
+	///  Defaults to `power-of-two`
+	#[arg(long, value_enum)]
+	policy: Option<Policy>,
+	/// The least scale of a block, such as 0.25 to never shrink blocks
+	/// below a quarter of their size
+	#[arg(long)]
+	min_scale: Option<f32>,
 	/// If image-2-image, force shrinking?
 	#[arg(long, default_value_t = false)]
 	force: bool,
+	/// Shrink to at most this size, in octets, searching for the shrinking
+	/// factor
+	#[arg(long, conflicts_with = "target_quality")]
+	target_size: Option<usize>,
+	/// Shrink to at least this quality, searching for the shrinking factor:
+	///  a PSNR in dB if above 1, or else an SSIM
+	#[arg(long)]
+	target_quality: Option<f32>,
+	/// A grayscale image of the importance of each region: white regions
+	///  are never shrunk, and black ones shrink freely. Shrinks even
+	///  without `--force`
+	#[arg(long, conflicts_with = "alpha_mask")]
+	mask: Option<PathBuf>,
+	/// Use the alpha channel of the input as the importance mask, as with
+	///  `--mask`
+	#[arg(long, default_value_t = false)]
+	alpha_mask: bool,
+	// Compression
+	/// The second stage compression of the `.pix` output
+	#[arg(long, value_enum)]
+	compression: Option<CompressionMethod>,
+	/// The level of the second stage compression.
+	///  If no compression is given, uses zstd.
+	#[arg(short = 'z', long)]
+	compression_level: Option<i32>,
+	/// Embed a preview, of one average color per block, in the `.pix`
+	/// output
+	#[arg(long, default_value_t = false)]
+	preview: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Metric {
+	/// The mean absolute deviation of the Oklab samples
+	Deviation,
+	/// The horizontal and vertical Sobel gradients
+	Sobel,
+	/// The standard deviation of the Laplacian of the lightness
+	Laplacian,
+	/// The entropy of the lightness histogram
+	Entropy,
+	/// The share of high frequencies in the 8x8 DCT of the lightness
+	Dct,
+}
+
+/// The analyzer of the `metric`, or the one of the scan direction
+fn parse_metric(
+	metric: Option<Metric>,
+	direction_wise: Option<bool>,
+) -> Box<dyn BlockAnalyzer> {
+	let default = if direction_wise == Some(true) {
+		Metric::Sobel
+	} else {
+		Metric::Deviation
+	};
+	match metric.unwrap_or(default) {
+		Metric::Deviation => Box::new(OklabDeviation::default()),
+		Metric::Sobel => Box::new(Sobel),
+		Metric::Laplacian => Box::new(Laplacian),
+		Metric::Entropy => Box::new(Entropy),
+		Metric::Dct => Box::new(DctEnergy),
+	}
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Policy {
+	/// Keeps each side whole, or halves it a number of times
+	PowerOfTwo,
+	/// Scales each side by the detail value
+	Continuous,
+}
+
+/// The shrink policy, never shrinking a block below `min_scale`
+fn parse_policy(
+	policy: Option<Policy>,
+	min_scale: Option<f32>,
+) -> Clamped<fn(f32) -> f32> {
+	let scale: fn(f32) -> f32 = match policy {
+		Some(Policy::Continuous) => |value| Continuous.scale(value),
+		Some(Policy::PowerOfTwo) | None => |value| PowerOfTwo.scale(value),
+	};
+	// Within [0; 1], which `Clamped` needs
+	let min_scale = min_scale
+		.filter(|min_scale| !min_scale.is_nan())
+		.unwrap_or(0.)
+		.clamp(0., 1.);
+	Clamped::new(scale, min_scale, 1.)
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CompressionMethod {
+	None,
+	Deflate,
+	Zstd,
+}
+
+fn parse_compression(
+	method: Option<CompressionMethod>,
+	level: Option<i32>,
+) -> Compression {
+	match (method, level) {
+		(None, None) | (Some(CompressionMethod::None), _) => {
+			Compression::None
+		}
+		(Some(CompressionMethod::Deflate), level) => Compression::Deflate(
+			level.map_or(Compression::DEFAULT_DEFLATE_LEVEL, |level| {
+				level.clamp(0, 9) as u32
+			}),
+		),
+		(Some(CompressionMethod::Zstd) | None, level) => {
+			Compression::Zstd(
+				level.unwrap_or(Compression::DEFAULT_ZSTD_LEVEL),
+			)
+		}
+	}
 }
 
 const DEFAULT_SHRINKING_FACTOR: f32 = 1f32;
@@ -67,6 +216,103 @@ fn parse_shrinking_factor(shrinking_factor: &str) -> f32 {
 		* (if invert { 1.0 / factor } else { factor })
 }
 
+/// The size or quality to shrink to, if any is given
+fn parse_target(
+	size: Option<usize>,
+	quality: Option<f32>,
+) -> Option<Target> {
+	match (size, quality) {
+		(Some(size), _) => Some(Target::Bytes(size)),
+		(None, Some(psnr)) if psnr > 1. => Some(Target::Psnr(psnr)),
+		(None, Some(ssim)) => Some(Target::Ssim(ssim)),
+		(None, None) => None,
+	}
+}
+
+/// Shrinks the image to the `target`, if any is given, or else by the
+/// `shrink_by` factor, if any is given, measuring its blocks with the
+/// `analyzer`, and scaling them as the `policy` gives, weighted by the
+/// `mask`, if any
+fn shrink_pix(
+	pix: &mut Pixlzr,
+	filter: FilterType,
+	analyzer: &dyn BlockAnalyzer,
+	policy: &impl ShrinkPolicy,
+	shrink_by: Option<f32>,
+	target: Option<Target>,
+	mask: Option<&GrayImage>,
+) -> Result<()> {
+	match (target, shrink_by) {
+		(Some(_), _) if mask.is_some() => {
+			bail!("Masks can't be combined with a target size or quality");
+		}
+		(Some(target), _) => {
+			let result = pix
+				.shrink_to_target_with(filter, analyzer, target, policy)?;
+			println!(
+				"Shrinking factor: {}, size: {} octets, PSNR: {:.2} dB, SSIM: {:.4}",
+				result.factor,
+				result.size,
+				result.quality.psnr,
+				result.quality.ssim
+			);
+		}
+		(None, Some(shrink_by)) => match mask {
+			Some(mask) => pix.shrink_with_mask(
+				filter, mask, analyzer, shrink_by, policy,
+			)?,
+			None => pix.shrink_with(filter, analyzer, shrink_by, policy),
+		},
+		(None, None) => {}
+	}
+	Ok(())
+}
+
+/// Fails if a target or a mask is given for an animation
+fn check_animation_options(
+	target: Option<Target>,
+	masked: bool,
+) -> Result<()> {
+	if target.is_some() {
+		bail!(
+			"Target sizes and qualities aren't supported for animations"
+		);
+	}
+	if masked {
+		bail!("Masks aren't supported for animations");
+	}
+	Ok(())
+}
+
+/// The importance mask of `img`: its alpha channel if `alpha`, or else
+/// the image at `path`, resized to `img`, if any
+fn load_mask(
+	path: Option<&Path>,
+	alpha: bool,
+	img: &DynamicImage,
+) -> Result<Option<GrayImage>> {
+	if alpha {
+		return Ok(Some(alpha_mask(img)));
+	}
+	let Some(path) = path else {
+		return Ok(None);
+	};
+	let mask = open(path)
+		.with_context(|| format_file_error(IMG_OPEN_ERROR, path))?
+		.to_luma8();
+	let (width, height) = (img.width(), img.height());
+	Ok(Some(if mask.dimensions() == (width, height) {
+		mask
+	} else {
+		imageops::resize(
+			&mask,
+			width,
+			height,
+			imageops::FilterType::Triangle,
+		)
+	}))
+}
+
 fn main() -> Result<()> {
 	let mut args = CliArgs::parse();
 	// println!("{:#?}", args);
@@ -139,6 +385,113 @@ fn format_file_error(base: &str, file: &Path) -> String {
 	format!("{} [ {} ]", base, file.to_str().unwrap())
 }
 
+/// Opens the image at `path`, along with the metadata its decoder supports
+fn open_with_metadata(path: &Path) -> Result<(DynamicImage, Metadata)> {
+	let mut decoder = ImageReader::open(path)?.into_decoder()?;
+	let metadata = Metadata::from_decoder(&mut decoder)?;
+	Ok((DynamicImage::from_decoder(decoder)?, metadata))
+}
+
+/// Saves the image at `path`, with the format derived from its extension,
+/// along with the metadata its encoder supports
+fn save_with_metadata(
+	img: &DynamicImage,
+	path: &Path,
+	metadata: &Metadata,
+) -> Result<()> {
+	fn write_with(
+		img: &DynamicImage,
+		mut encoder: impl ImageEncoder,
+		metadata: &Metadata,
+	) -> Result<()> {
+		metadata.apply_to_encoder(&mut encoder);
+		Ok(img.write_with_encoder(encoder)?)
+	}
+	let format = ImageFormat::from_path(path)?;
+	if !matches!(
+		format,
+		ImageFormat::Png
+			| ImageFormat::Jpeg
+			| ImageFormat::WebP
+			| ImageFormat::Tiff
+	) {
+		return Ok(img.save_with_format(path, format)?);
+	}
+
+	let file = BufWriter::new(File::create(path)?);
+	match format {
+		ImageFormat::Png => {
+			write_with(img, PngEncoder::new(file), metadata)
+		}
+		ImageFormat::Jpeg => {
+			write_with(img, JpegEncoder::new(file), metadata)
+		}
+		ImageFormat::WebP => {
+			write_with(img, WebPEncoder::new_lossless(file), metadata)
+		}
+		_ => write_with(img, TiffEncoder::new(file), metadata),
+	}
+}
+
+/// Opens the animated GIF or PNG at `path` as a sequence, split in blocks
+/// of `block_width` x `block_height` pixels.
+///
+/// Returns `None` for other formats, and for animations of a single frame.
+fn open_animation(
+	path: &Path,
+	block_width: u32,
+	block_height: u32,
+) -> Result<Option<PixlzrSequence>> {
+	let file = BufReader::new(File::open(path)?);
+	let (frames, metadata) = match ImageFormat::from_path(path) {
+		Ok(ImageFormat::Gif) => {
+			let mut decoder = GifDecoder::new(file)?;
+			let metadata = Metadata::from_decoder(&mut decoder)?;
+			(decoder.into_frames(), metadata)
+		}
+		Ok(ImageFormat::Png) => {
+			let mut decoder = PngDecoder::new(file)?;
+			if !decoder.is_apng()? {
+				return Ok(None);
+			}
+			let metadata = Metadata::from_decoder(&mut decoder)?;
+			(decoder.apng()?.into_frames(), metadata)
+		}
+		_ => return Ok(None),
+	};
+	let mut sequence =
+		PixlzrSequence::from_animation(frames, block_width, block_height)?;
+	if sequence.len() < 2 {
+		return Ok(None);
+	}
+	sequence.metadata = metadata;
+	Ok(Some(sequence))
+}
+
+/// Saves the sequence at `path` as an animated GIF or PNG, as given by its
+/// extension
+fn save_animation(
+	sequence: &PixlzrSequence,
+	path: &Path,
+	filter: FilterType,
+) -> Result<()> {
+	let format = ImageFormat::from_path(path)?;
+	let file = BufWriter::new(File::create(path)?);
+	match format {
+		ImageFormat::Gif => sequence.write_gif(file, filter)?,
+		ImageFormat::Png => sequence.write_apng(file, filter)?,
+		_ => bail!("Animations can only be saved as GIF or PNG"),
+	}
+	Ok(())
+}
+
+/// Checks if the `.pix` file at `path` holds a sequence of frames
+fn is_sequence(path: &Path) -> Result<bool> {
+	let mut magic = [0u8; 6];
+	let read = File::open(path)?.read(&mut magic)?;
+	Ok(magic[..read] == *PIXLZR_SEQUENCE_MAGIC_NUMBERS)
+}
+
 fn image_to_pix(
 	CliArgs {
 		input,
@@ -147,27 +500,67 @@ fn image_to_pix(
 		block_height,
 		filter,
 		direction_wise,
+		metric,
+		policy,
+		min_scale,
 		shrinking_factor: _,
 		force,
+		target_size,
+		target_quality,
+		mask,
+		alpha_mask,
+		compression,
+		compression_level,
+		preview,
 	}: CliArgs,
 	shrink_by: f32,
 ) -> Result<()> {
-	let img = open(&input)
+	let block_height = block_height.unwrap_or(block_width);
+	let target = parse_target(target_size, target_quality);
+	let analyzer = parse_metric(metric, direction_wise);
+	let policy = parse_policy(policy, min_scale);
+	if let Some(mut sequence) =
+		open_animation(&input, block_width, block_height)
+			.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?
+	{
+		check_animation_options(target, mask.is_some() || alpha_mask)?;
+		if force {
+			sequence.shrink_with(
+				filter,
+				analyzer.as_ref(),
+				shrink_by,
+				&policy,
+			);
+		}
+		sequence.compression =
+			parse_compression(compression, compression_level);
+		for frame in sequence.frames.iter_mut() {
+			frame.image.preview = preview;
+		}
+		sequence
+			.save(&output)
+			.with_context(|| format_file_error(IMG_SAVE_ERROR, &output))?;
+		return Ok(());
+	}
+
+	let (img, metadata) = open_with_metadata(&input)
 		.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?;
 
-	let mut pix = Pixlzr::from_image(
-		&img,
-		block_width,
-		block_height.unwrap_or(block_width),
-	);
+	let mask = load_mask(mask.as_deref(), alpha_mask, &img)?;
+	let mut pix = Pixlzr::from_image(&img, block_width, block_height);
+	pix.metadata = metadata;
+	pix.compression = parse_compression(compression, compression_level);
+	pix.preview = preview;
 
-	if force {
-		if direction_wise.unwrap() {
-			pix.shrink_directionally(filter, shrink_by);
-		} else {
-			pix.shrink_by(filter, shrink_by);
-		}
-	}
+	shrink_pix(
+		&mut pix,
+		filter,
+		analyzer.as_ref(),
+		&policy,
+		(force || mask.is_some()).then_some(shrink_by),
+		target,
+		mask.as_ref(),
+	)?;
 
 	pix.save(&output)
 		.with_context(|| format_file_error(IMG_SAVE_ERROR, &output))?;
@@ -182,51 +575,120 @@ fn image_to_image(
 		block_height,
 		filter,
 		direction_wise,
+		metric,
+		policy,
+		min_scale,
 		shrinking_factor: _,
 		force,
+		target_size,
+		target_quality,
+		mask,
+		alpha_mask,
+		compression: _,
+		compression_level: _,
+		preview: _,
 	}: CliArgs,
 	shrink_by: f32,
 ) -> Result<()> {
-	let img = open(&input)
+	let block_height = block_height.unwrap_or(block_width);
+	let target = parse_target(target_size, target_quality);
+	let analyzer = parse_metric(metric, direction_wise);
+	let policy = parse_policy(policy, min_scale);
+	if let Some(mut sequence) =
+		open_animation(&input, block_width, block_height)
+			.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?
+	{
+		check_animation_options(target, mask.is_some() || alpha_mask)?;
+		if force {
+			sequence.shrink_with(
+				filter,
+				analyzer.as_ref(),
+				shrink_by,
+				&policy,
+			);
+		}
+		save_animation(&sequence, &output, filter)
+			.with_context(|| format_file_error(IMG_SAVE_ERROR, &output))?;
+		return Ok(());
+	}
+
+	let (img, metadata) = open_with_metadata(&input)
 		.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?;
 
-	let mut pix = Pixlzr::from_image(
-		&img,
-		block_width,
-		block_height.unwrap_or(block_width),
-	);
+	let mask = load_mask(mask.as_deref(), alpha_mask, &img)?;
+	let mut pix = Pixlzr::from_image(&img, block_width, block_height);
 
-	if force {
-		if direction_wise.unwrap() {
-			pix.shrink_directionally(filter, shrink_by);
-		} else {
-			pix.shrink_by(filter, shrink_by);
-		}
-	}
+	shrink_pix(
+		&mut pix,
+		filter,
+		analyzer.as_ref(),
+		&policy,
+		(force || mask.is_some()).then_some(shrink_by),
+		target,
+		mask.as_ref(),
+	)?;
 
 	let img = pix.to_image(filter);
-	img.save(&output)
+	save_with_metadata(&img, &output, &metadata)
 		.with_context(|| format_file_error(IMG_SAVE_ERROR, &output))?;
 	Ok(())
 }
 
 fn pix_to_image(args: &CliArgs, shrink_by: f32) -> Result<()> {
 	let filter = args.filter;
+	let target = parse_target(args.target_size, args.target_quality);
+	let analyzer = parse_metric(args.metric, args.direction_wise);
+	let policy = parse_policy(args.policy, args.min_scale);
+	if is_sequence(&args.input)
+		.with_context(|| format_file_error(IMG_OPEN_ERROR, &args.input))?
+	{
+		let mut sequence = PixlzrSequence::open(&args.input)
+			.with_context(|| {
+				format_file_error(IMG_OPEN_ERROR, &args.input)
+			})?;
+		check_animation_options(
+			target,
+			args.mask.is_some() || args.alpha_mask,
+		)?;
+		if args.force {
+			sequence.shrink_with(
+				filter,
+				analyzer.as_ref(),
+				shrink_by,
+				&policy,
+			);
+		}
+		save_animation(&sequence, &args.output, filter).with_context(
+			|| format_file_error(IMG_SAVE_ERROR, &args.output),
+		)?;
+		return Ok(());
+	}
 	let mut pix = Pixlzr::open(&args.input)
 		.with_context(|| format_file_error(IMG_OPEN_ERROR, &args.input))?;
+	let mask = if args.mask.is_some() || args.alpha_mask {
+		load_mask(
+			args.mask.as_deref(),
+			args.alpha_mask,
+			&pix.to_image(filter),
+		)?
+	} else {
+		None
+	};
 
-	if args.force {
-		if args.direction_wise.unwrap() {
-			pix.shrink_directionally(filter, shrink_by);
-		} else {
-			pix.shrink_by(filter, shrink_by);
-		}
-	}
+	shrink_pix(
+		&mut pix,
+		filter,
+		analyzer.as_ref(),
+		&policy,
+		(args.force || mask.is_some()).then_some(shrink_by),
+		target,
+		mask.as_ref(),
+	)?;
 
 	let img = pix.to_image(filter);
-	img.save(&args.output).with_context(|| {
-		format_file_error(IMG_SAVE_ERROR, &args.output)
-	})?;
+	save_with_metadata(&img, &args.output, &pix.metadata).with_context(
+		|| format_file_error(IMG_SAVE_ERROR, &args.output),
+	)?;
 	Ok(())
 }
 
@@ -238,35 +700,93 @@ fn pix_to_pix(
 		block_height,
 		filter,
 		direction_wise,
+		metric,
+		policy,
+		min_scale,
 		shrinking_factor: _,
 		force,
+		target_size,
+		target_quality,
+		mask,
+		alpha_mask,
+		compression,
+		compression_level,
+		preview,
 	}: CliArgs,
 	shrink_by: f32,
 ) -> Result<()> {
-	let mut pix = Pixlzr::from_image(
-		&Pixlzr::open(&input)
-			.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?
-			.to_image(filter),
-		block_width,
-		block_height.unwrap_or(block_width),
-	);
+	let block_height = block_height.unwrap_or(block_width);
+	let target = parse_target(target_size, target_quality);
+	let analyzer = parse_metric(metric, direction_wise);
+	let policy = parse_policy(policy, min_scale);
+	if is_sequence(&input)
+		.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?
+	{
+		check_animation_options(target, mask.is_some() || alpha_mask)?;
+		let orig = PixlzrSequence::open(&input)
+			.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?;
+		let (width, height) = orig.dimensions();
+		let mut sequence =
+			PixlzrSequence::new(width, height, block_width, block_height);
+		for frame in orig.iter() {
+			sequence
+				.push_image(&frame.image.to_image(filter), frame.delay)?;
+		}
+		sequence.metadata = orig.metadata;
 
-	if force {
-		if direction_wise.unwrap() {
-			pix.shrink_directionally(filter, shrink_by);
-		} else {
-			pix.shrink_by(filter, shrink_by);
+		if force {
+			sequence.shrink_with(
+				filter,
+				analyzer.as_ref(),
+				shrink_by,
+				&policy,
+			);
+		}
+
+		sequence.compression =
+			parse_compression(compression, compression_level);
+		for frame in sequence.frames.iter_mut() {
+			frame.image.preview = preview;
 		}
+		sequence
+			.save(&output)
+			.with_context(|| format_file_error(IMG_SAVE_ERROR, &output))?;
+		return Ok(());
 	}
 
+	let orig = Pixlzr::open(&input)
+		.with_context(|| format_file_error(IMG_OPEN_ERROR, &input))?;
+	let img = orig.to_image(filter);
+	let mask = load_mask(mask.as_deref(), alpha_mask, &img)?;
+	let mut pix = Pixlzr::from_image(&img, block_width, block_height);
+	pix.metadata = orig.metadata;
+	pix.compression = parse_compression(compression, compression_level);
+	pix.preview = preview;
+
+	shrink_pix(
+		&mut pix,
+		filter,
+		analyzer.as_ref(),
+		&policy,
+		(force || mask.is_some()).then_some(shrink_by),
+		target,
+		mask.as_ref(),
+	)?;
+
 	pix.save(&output)
 		.with_context(|| format_file_error(IMG_SAVE_ERROR, &output))?;
 	Ok(())
 }
 
+#[path = "../data_types/test_images.rs"]
+mod test_images;
+
 mod tests_on_binary {
 	use image::GenericImageView;
 
+	#[allow(unused_imports)]
+	use super::test_images::noise;
+
 	use super::*;
 
 	#[allow(dead_code)]
@@ -296,6 +816,47 @@ mod tests_on_binary {
 		);
 	}
 
+	#[test]
+	fn test_cli_parse_target() {
+		assert_eq!(parse_target(None, None), None);
+		assert_eq!(
+			parse_target(Some(200_000), None),
+			Some(Target::Bytes(200_000))
+		);
+		assert_eq!(parse_target(None, Some(38.)), Some(Target::Psnr(38.)));
+		assert_eq!(
+			parse_target(None, Some(0.95)),
+			Some(Target::Ssim(0.95))
+		);
+	}
+
+	#[test]
+	fn test_cli_parse_policy() {
+		assert_eq!(parse_policy(None, None).scale(0.3), 0.25);
+		assert_eq!(parse_policy(None, Some(0.5)).scale(0.), 0.5);
+		let continuous = Some(Policy::Continuous);
+		assert_eq!(parse_policy(continuous, None).scale(0.3), 0.3);
+		assert_eq!(parse_policy(continuous, Some(2.)).scale(0.3), 1.);
+	}
+
+	#[test]
+	fn test_cli_parse_compression() {
+		assert_eq!(parse_compression(None, None), Compression::None);
+		assert_eq!(
+			parse_compression(Some(CompressionMethod::None), Some(5)),
+			Compression::None
+		);
+		assert_eq!(parse_compression(None, Some(9)), Compression::Zstd(9));
+		assert_eq!(
+			parse_compression(Some(CompressionMethod::Deflate), Some(12)),
+			Compression::Deflate(9)
+		);
+		assert_eq!(
+			parse_compression(Some(CompressionMethod::Zstd), None),
+			Compression::Zstd(Compression::DEFAULT_ZSTD_LEVEL)
+		);
+	}
+
 	#[test]
 	fn test_image_to_image() {
 		let path_orig: PathBuf = "image.png".into();
@@ -354,4 +915,128 @@ mod tests_on_binary {
 
 		std::fs::remove_file(path_new).unwrap();
 	}
+
+	#[test]
+	fn test_metadata_through_pix() {
+		let path_orig: PathBuf = "test_metadata.png".into();
+		let path_pix: PathBuf = "test_metadata.pix".into();
+		let path_new: PathBuf = "test_metadata_from_pix.png".into();
+
+		// An image with an ICC profile and EXIF data
+		let img =
+			image::RgbImage::from_pixel(20, 10, image::Rgb([9, 8, 7]));
+		let mut encoder = PngEncoder::new(BufWriter::new(
+			File::create(&path_orig).unwrap(),
+		));
+		encoder.set_icc_profile(vec![1; 64]).unwrap();
+		encoder
+			.set_exif_metadata(b"MM\0*\0\0\0\x08\0\0".to_vec())
+			.unwrap();
+		DynamicImage::ImageRgb8(img)
+			.write_with_encoder(encoder)
+			.unwrap();
+		let (_, metadata) = open_with_metadata(&path_orig).unwrap();
+		assert!(metadata.icc.is_some() && metadata.exif.is_some());
+
+		let args = |input: &PathBuf, output: &PathBuf| CliArgs {
+			input: input.clone(),
+			output: output.clone(),
+			block_width: 8,
+			..Default::default()
+		};
+		image_to_pix(args(&path_orig, &path_pix), 0.0).unwrap();
+		assert_eq!(Pixlzr::open(&path_pix).unwrap().metadata, metadata);
+		pix_to_image(&args(&path_pix, &path_new), 0.0).unwrap();
+		assert_eq!(open_with_metadata(&path_new).unwrap().1, metadata);
+
+		for path in [path_orig, path_pix, path_new] {
+			std::fs::remove_file(path).unwrap();
+		}
+	}
+
+	#[test]
+	fn test_mask_without_force() {
+		let path_orig: PathBuf = "test_mask.png".into();
+		let path_pix: PathBuf = "test_mask.pix".into();
+
+		// An opaque, noisy left half, and a transparent right half
+		let img = image::RgbaImage::from_fn(32, 16, |x, y| {
+			let noise = noise(x, y, 97) as u8;
+			image::Rgba([noise, 40, 90, if x < 16 { 255 } else { 0 }])
+		});
+		DynamicImage::ImageRgba8(img).save(&path_orig).unwrap();
+
+		image_to_pix(
+			CliArgs {
+				input: path_orig.clone(),
+				output: path_pix.clone(),
+				block_width: 16,
+				alpha_mask: true,
+				..Default::default()
+			},
+			0.01,
+		)
+		.unwrap();
+		let pix = Pixlzr::open(&path_pix).unwrap();
+		assert_eq!(pix.blocks[0].dimensions(), (16, 16));
+		assert!(pix.blocks[1].width() < 16);
+
+		for path in [path_orig, path_pix] {
+			std::fs::remove_file(path).unwrap();
+		}
+	}
+
+	#[test]
+	fn test_animation_through_pix() {
+		use image::{codecs::gif::GifEncoder, Delay, Frame, RgbaImage};
+
+		let path_orig: PathBuf = "test_animation.gif".into();
+		let path_pix: PathBuf = "test_animation.pix".into();
+		let path_new: PathBuf = "test_animation_from_pix.png".into();
+
+		let frames = (0..3u8).map(|i| {
+			Frame::from_parts(
+				RgbaImage::from_pixel(
+					16,
+					8,
+					image::Rgba([i * 90, 0, 0, 255]),
+				),
+				0,
+				0,
+				Delay::from_numer_denom_ms(50 * (i as u32 + 1), 1),
+			)
+		});
+		GifEncoder::new(File::create(&path_orig).unwrap())
+			.encode_frames(frames)
+			.unwrap();
+
+		let args = |input: &PathBuf, output: &PathBuf| CliArgs {
+			input: input.clone(),
+			output: output.clone(),
+			block_width: 8,
+			..Default::default()
+		};
+		image_to_pix(args(&path_orig, &path_pix), 0.0).unwrap();
+		assert!(is_sequence(&path_pix).unwrap());
+		assert_eq!(PixlzrSequence::open(&path_pix).unwrap().len(), 3);
+
+		pix_to_image(&args(&path_pix, &path_new), 0.0).unwrap();
+		let decoder = PngDecoder::new(BufReader::new(
+			File::open(&path_new).unwrap(),
+		))
+		.unwrap();
+		let frames = decoder
+			.apng()
+			.unwrap()
+			.into_frames()
+			.collect_frames()
+			.unwrap();
+		let delays: Vec<_> =
+			frames.iter().map(|f| f.delay().numer_denom_ms()).collect();
+		assert_eq!(delays, [(50, 1), (100, 1), (150, 1)]);
+
+		for path in [path_orig, path_pix, path_new] {
+			std::fs::remove_file(path).unwrap();
+		}
+	}
 }
@@ -11,7 +11,7 @@
 use anyhow::Result;
 #[cfg(feature = "image-rs")]
 use image::open;
-use pixlzr::{FilterType, Pixlzr};
+use pixlzr::{policy::PowerOfTwo, FilterType, Pixlzr};
 use std::fs;
 
 mod path {
@@ -145,7 +145,7 @@ fn write(
 	let img = open(path_in)?;
 	let mut pix = Pixlzr::from_image(&img, block_size, block_size);
 
-	pix.shrink_by(FilterType::Nearest, factor);
+	pix.shrink_by(FilterType::Nearest, factor, &PowerOfTwo);
 
 	pix.save(path_out)?;
 	Ok(pix)
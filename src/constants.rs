@@ -8,16 +8,26 @@ pub const QOI_MAGIC: &[u8] = b"qoif";
 pub const QOI_MAGIC_SIZE: usize = QOI_MAGIC.len();
 
 pub const PIXLZR_MAGIC_NUMBERS: &[u8] = b"PIXLZR";
-pub const PIXLZR_MAGIC_VERSION: &[u8] = &[0, 0, 2];
+pub const PIXLZR_MAGIC_VERSION: &[u8] = &[0, 1, 7];
 
 pub const PIXLZR_VERSION: Semver = Semver {
 	major: 0,
-	minor: 0,
-	patch: 2,
+	minor: 1,
+	patch: 7,
 };
 
 pub const PIXLZR_HEADER_SIZE: usize =
-	PIXLZR_MAGIC_NUMBERS.len() + PIXLZR_MAGIC_VERSION.len() + 4 * 4 + 1;
+	PIXLZR_MAGIC_NUMBERS.len() + PIXLZR_MAGIC_VERSION.len() + 4 * 4 + 3;
+
+/// The most octets a pixel can take in a line of blocks: its largest
+/// sample, and the overhead of a block of its own, in a quadtree
+pub const PIXLZR_MAX_PIXEL_OCTETS: usize = 48;
+
+/// The most octets a decoded block can take, as a few octets of a solid
+/// block can stand for a whole cell: 512 MiB
+pub const PIXLZR_MAX_BLOCK_OCTETS: usize = 1 << 29;
+
+pub const PIXLZR_SEQUENCE_MAGIC_NUMBERS: &[u8] = b"PIXSEQ";
 
 pub const PIXLZR_BLOCK_MAGIC_NUMBERS: &[u8] = b"block";
 pub const PIXLZR_BLOCK_HEADER_BASE_SIZE: usize =
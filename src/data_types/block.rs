@@ -1,4 +1,4 @@
-///! Contains the PixlzrBlock and ImageBlock structs
+//! Contains the PixlzrBlock and ImageBlock structs
 use std::slice::ChunksExact;
 
 /// ImageBlock
@@ -9,8 +9,9 @@ use std::slice::ChunksExact;
 ///     - width: u32
 ///     - height: u32
 ///     - block_value: Option<f32>
+///     - axis_values: Option<(f32, f32)>
 ///     - data: RawImage
-///       - alpha: bool
+///       - color: ColorType
 ///       - width: u32
 ///       - height: u32
 ///       - data: Vec<u8>
@@ -18,6 +19,7 @@ use std::slice::ChunksExact;
 ///     - width: u32
 ///     - height: u32
 ///     - block_value: Option<f32>
+///     - axis_values: Option<(f32, f32)>
 ///     - data: DynamicImage
 ///
 /// PixlzrBlockRaw <-> PixlzrBlockImage
@@ -26,6 +28,7 @@ use std::slice::ChunksExact;
 ///
 /// - PixlzrBlock(Raw) -> PixlzrBlockRaw
 /// - PixlzrBlock(Image) -> PixlzrBlockRaw
+///
 /// PixlzrBlock.into() -> PixlzrBlock::{Raw, Image}
 use super::FilterType as P_FilterType;
 
@@ -34,8 +37,8 @@ use fast_image_resize::{PixelType as FIR_PixelType, Resizer};
 
 #[cfg(feature = "image-rs")]
 use image::{
-	imageops::FilterType as I_FilterType, DynamicImage, RgbImage,
-	RgbaImage,
+	imageops::FilterType as I_FilterType, DynamicImage, GrayAlphaImage,
+	GrayImage, RgbImage, RgbaImage,
 };
 
 /// Image block representation, with:
@@ -47,14 +50,201 @@ pub struct ImageBlock {
 	pub block: PixlzrBlock,
 }
 
+/// The type of each sample (channel) of a pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum SampleType {
+	#[default]
+	U8,
+	U16,
+	F32,
+}
+
+impl SampleType {
+	/// The size of a sample, in octets
+	#[inline]
+	pub fn size(self) -> usize {
+		match self {
+			SampleType::U8 => 1,
+			SampleType::U16 => 2,
+			SampleType::F32 => 4,
+		}
+	}
+}
+
+/// The layout of each pixel of a block, as the `image` crate's color
+/// types.
+///
+/// The samples are kept in the native endianness.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ColorType {
+	/// Grayscale
+	L8 = 1,
+	/// Grayscale, with alpha
+	La8 = 2,
+	/// RGB
+	#[default]
+	Rgb8 = 3,
+	/// RGB, with alpha
+	Rgba8 = 4,
+	/// Grayscale, of 16 bits per sample
+	L16 = 5,
+	/// Grayscale, with alpha, of 16 bits per sample
+	La16 = 6,
+	/// RGB, of 16 bits per sample
+	Rgb16 = 7,
+	/// RGB, with alpha, of 16 bits per sample
+	Rgba16 = 8,
+	/// RGB, of 32 bits floats per sample
+	Rgb32F = 9,
+	/// RGB, with alpha, of 32 bits floats per sample
+	Rgba32F = 10,
+}
+
+impl ColorType {
+	#[inline]
+	pub fn channels(self) -> usize {
+		match self {
+			ColorType::L8 | ColorType::L16 => 1,
+			ColorType::La8 | ColorType::La16 => 2,
+			ColorType::Rgb8 | ColorType::Rgb16 | ColorType::Rgb32F => 3,
+			ColorType::Rgba8 | ColorType::Rgba16 | ColorType::Rgba32F => 4,
+		}
+	}
+	#[inline]
+	pub fn sample_type(self) -> SampleType {
+		match self {
+			ColorType::L8
+			| ColorType::La8
+			| ColorType::Rgb8
+			| ColorType::Rgba8 => SampleType::U8,
+			ColorType::L16
+			| ColorType::La16
+			| ColorType::Rgb16
+			| ColorType::Rgba16 => SampleType::U16,
+			ColorType::Rgb32F | ColorType::Rgba32F => SampleType::F32,
+		}
+	}
+	/// The size of a pixel, in octets
+	#[inline]
+	pub fn bytes_per_pixel(self) -> usize {
+		self.channels() * self.sample_type().size()
+	}
+	#[inline]
+	pub fn has_alpha(self) -> bool {
+		self.channels().is_multiple_of(2)
+	}
+	#[inline]
+	pub fn is_gray(self) -> bool {
+		self.channels() < 3
+	}
+	pub fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			1 => Some(ColorType::L8),
+			2 => Some(ColorType::La8),
+			3 => Some(ColorType::Rgb8),
+			4 => Some(ColorType::Rgba8),
+			5 => Some(ColorType::L16),
+			6 => Some(ColorType::La16),
+			7 => Some(ColorType::Rgb16),
+			8 => Some(ColorType::Rgba16),
+			9 => Some(ColorType::Rgb32F),
+			10 => Some(ColorType::Rgba32F),
+			_ => None,
+		}
+	}
+	/// The color type with the given `sample` type, which is gray and has
+	/// alpha as requested.
+	///
+	/// As there are no gray floats, those are RGB.
+	pub fn with(sample: SampleType, gray: bool, alpha: bool) -> Self {
+		match (sample, gray, alpha) {
+			(SampleType::U8, true, false) => ColorType::L8,
+			(SampleType::U8, true, true) => ColorType::La8,
+			(SampleType::U8, false, false) => ColorType::Rgb8,
+			(SampleType::U8, false, true) => ColorType::Rgba8,
+			(SampleType::U16, true, false) => ColorType::L16,
+			(SampleType::U16, true, true) => ColorType::La16,
+			(SampleType::U16, false, false) => ColorType::Rgb16,
+			(SampleType::U16, false, true) => ColorType::Rgba16,
+			(SampleType::F32, _, false) => ColorType::Rgb32F,
+			(SampleType::F32, _, true) => ColorType::Rgba32F,
+		}
+	}
+	/// The color type that better keeps an image of `color`
+	#[cfg(feature = "image-rs")]
+	pub fn from_image_color(color: image::ColorType) -> Self {
+		use image::ColorType as I;
+		match color {
+			I::L8 => ColorType::L8,
+			I::La8 => ColorType::La8,
+			I::Rgb8 => ColorType::Rgb8,
+			I::Rgba8 => ColorType::Rgba8,
+			I::L16 => ColorType::L16,
+			I::La16 => ColorType::La16,
+			I::Rgb16 => ColorType::Rgb16,
+			I::Rgba16 => ColorType::Rgba16,
+			I::Rgb32F => ColorType::Rgb32F,
+			I::Rgba32F => ColorType::Rgba32F,
+			color => {
+				let sample = match color.bytes_per_pixel()
+					/ color.channel_count()
+				{
+					1 => SampleType::U8,
+					2 => SampleType::U16,
+					_ => SampleType::F32,
+				};
+				Self::with(sample, !color.has_color(), color.has_alpha())
+			}
+		}
+	}
+	/// Converts the `image` into this color type, if it isn't already
+	#[cfg(feature = "image-rs")]
+	pub fn convert_image(self, image: DynamicImage) -> DynamicImage {
+		if image.color() == self.into() {
+			return image;
+		}
+		match self {
+			ColorType::L8 => image.into_luma8().into(),
+			ColorType::La8 => image.into_luma_alpha8().into(),
+			ColorType::Rgb8 => image.into_rgb8().into(),
+			ColorType::Rgba8 => image.into_rgba8().into(),
+			ColorType::L16 => image.into_luma16().into(),
+			ColorType::La16 => image.into_luma_alpha16().into(),
+			ColorType::Rgb16 => image.into_rgb16().into(),
+			ColorType::Rgba16 => image.into_rgba16().into(),
+			ColorType::Rgb32F => image.into_rgb32f().into(),
+			ColorType::Rgba32F => image.into_rgba32f().into(),
+		}
+	}
+}
+
+#[cfg(feature = "image-rs")]
+impl From<ColorType> for image::ColorType {
+	fn from(value: ColorType) -> Self {
+		match value {
+			ColorType::L8 => image::ColorType::L8,
+			ColorType::La8 => image::ColorType::La8,
+			ColorType::Rgb8 => image::ColorType::Rgb8,
+			ColorType::Rgba8 => image::ColorType::Rgba8,
+			ColorType::L16 => image::ColorType::L16,
+			ColorType::La16 => image::ColorType::La16,
+			ColorType::Rgb16 => image::ColorType::Rgb16,
+			ColorType::Rgba16 => image::ColorType::Rgba16,
+			ColorType::Rgb32F => image::ColorType::Rgb32F,
+			ColorType::Rgba32F => image::ColorType::Rgba32F,
+		}
+	}
+}
+
 #[derive(Clone, Debug)]
 /// Raw image representation, with:
 /// - `width: u32` as the width of the image
 /// - `height: u32` as the height of the image
-/// - `alpha: bool` indicating if there is an alpha channel
+/// - `color: ColorType` as the layout of each pixel
 /// - `data: Vec<u8>` as the raw pixel data
 pub struct RawImage {
-	pub alpha: bool,
+	pub color: ColorType,
 	// pub width: u32,
 	// pub height: u32,
 	pub data: Vec<u8>,
@@ -68,6 +258,8 @@ pub struct RawImage {
 /// * `width` - The width of the image block.
 /// * `height` - The height of the image block.
 /// * `block_value` - An optional value representing the block's computed value.
+/// * `axis_values` - The horizontal and vertical values, for a block shrunk
+///   along each axis separately.
 /// * `data` - The raw pixel data encapsulated in a `RawImage`.
 ///
 /// The `PixlzrBlockRaw` struct is part of the `PixlzrBlock` enum and is used
@@ -77,6 +269,7 @@ pub struct PixlzrBlockRaw {
 	pub width: u32,
 	pub height: u32,
 	pub block_value: Option<f32>,
+	pub axis_values: Option<(f32, f32)>,
 	pub data: RawImage,
 }
 
@@ -89,6 +282,8 @@ pub struct PixlzrBlockRaw {
 /// * `width` - The width of the image block.
 /// * `height` - The height of the image block.
 /// * `block_value` - An optional value representing the block's computed value.
+/// * `axis_values` - The horizontal and vertical values, for a block shrunk
+///   along each axis separately.
 /// * `data` - The pixel data encapsulated in a `DynamicImage`.
 ///
 /// The `PixlzrBlockImage` struct is part of the `PixlzrBlock` enum and is used
@@ -98,6 +293,7 @@ pub struct PixlzrBlockImage {
 	pub width: u32,
 	pub height: u32,
 	pub block_value: Option<f32>,
+	pub axis_values: Option<(f32, f32)>,
 	pub data: DynamicImage,
 }
 
@@ -138,23 +334,84 @@ impl From<PixlzrBlock> for PixlzrBlockImage {
 			PixlzrBlock::Raw(raw) => {
 				let (width, height, img) =
 					(raw.width, raw.height, raw.data);
-				let buf = img.data;
-				let data: DynamicImage = if img.alpha {
-					RgbaImage::from_raw(width, height, buf).unwrap().into()
-				} else {
-					RgbImage::from_raw(width, height, buf).unwrap().into()
-				};
+				let data = raw_image_to_dynamic(width, height, img);
 				Self {
 					width,
 					height,
 					data,
 					block_value: raw.block_value,
+					axis_values: raw.axis_values,
 				}
 			}
 		}
 	}
 }
 
+/// Builds the DynamicImage of the pixels in `raw`, of `width` x `height`
+#[cfg(feature = "image-rs")]
+fn raw_image_to_dynamic(
+	width: u32,
+	height: u32,
+	raw: RawImage,
+) -> DynamicImage {
+	use image::{ImageBuffer, Luma, LumaA, Rgb, Rgba};
+
+	let buf = raw.data;
+	let u16s = || -> Vec<u16> {
+		buf.chunks_exact(2)
+			.map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+			.collect()
+	};
+	let f32s = || -> Vec<f32> {
+		buf.chunks_exact(4)
+			.map(|sample| {
+				f32::from_ne_bytes([
+					sample[0], sample[1], sample[2], sample[3],
+				])
+			})
+			.collect()
+	};
+	let image: Option<DynamicImage> = match raw.color {
+		ColorType::L8 => {
+			GrayImage::from_raw(width, height, buf).map(Into::into)
+		}
+		ColorType::La8 => {
+			GrayAlphaImage::from_raw(width, height, buf).map(Into::into)
+		}
+		ColorType::Rgb8 => {
+			RgbImage::from_raw(width, height, buf).map(Into::into)
+		}
+		ColorType::Rgba8 => {
+			RgbaImage::from_raw(width, height, buf).map(Into::into)
+		}
+		ColorType::L16 => {
+			ImageBuffer::<Luma<u16>, _>::from_raw(width, height, u16s())
+				.map(Into::into)
+		}
+		ColorType::La16 => {
+			ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, u16s())
+				.map(Into::into)
+		}
+		ColorType::Rgb16 => {
+			ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, u16s())
+				.map(Into::into)
+		}
+		ColorType::Rgba16 => {
+			ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, u16s())
+				.map(Into::into)
+		}
+		ColorType::Rgb32F => {
+			ImageBuffer::<Rgb<f32>, _>::from_raw(width, height, f32s())
+				.map(Into::into)
+		}
+		ColorType::Rgba32F => {
+			ImageBuffer::<Rgba<f32>, _>::from_raw(width, height, f32s())
+				.map(Into::into)
+		}
+	};
+	image.unwrap()
+}
+
 impl From<PixlzrBlock> for PixlzrBlockRaw {
 	fn from(value: PixlzrBlock) -> Self {
 		match value {
@@ -163,14 +420,16 @@ impl From<PixlzrBlock> for PixlzrBlockRaw {
 			PixlzrBlock::Image(image) => {
 				let (width, height, img) =
 					(image.width, image.height, image.data);
+				let color = ColorType::from_image_color(img.color());
 				let data = RawImage {
-					alpha: img.as_rgba8().is_some(),
-					data: img.into_bytes(),
+					color,
+					data: color.convert_image(img).into_bytes(),
 				};
 				Self {
 					width,
 					height,
 					block_value: image.block_value,
+					axis_values: image.axis_values,
 					data,
 				}
 			}
@@ -203,13 +462,32 @@ impl PixlzrBlock {
 			PixlzrBlock::Raw(block) => block.block_value,
 		}
 	}
-	pub fn has_alpha(&self) -> bool {
+	/// The horizontal and vertical values, if the block was shrunk along
+	/// each axis separately.
+	pub fn axis_values(&self) -> Option<(f32, f32)> {
+		match self {
+			#[cfg(feature = "image-rs")]
+			PixlzrBlock::Image(block) => block.axis_values,
+			PixlzrBlock::Raw(block) => block.axis_values,
+		}
+	}
+	pub fn color(&self) -> ColorType {
 		match self {
-			PixlzrBlock::Raw(raw) => raw.data.alpha,
+			PixlzrBlock::Raw(raw) => raw.data.color,
 			#[cfg(feature = "image-rs")]
-			PixlzrBlock::Image(img) => img.data.color().has_alpha(),
+			PixlzrBlock::Image(img) => {
+				ColorType::from_image_color(img.data.color())
+			}
 		}
 	}
+	#[inline]
+	pub fn channels(&self) -> usize {
+		self.color().channels()
+	}
+	#[inline]
+	pub fn has_alpha(&self) -> bool {
+		self.color().has_alpha()
+	}
 	pub fn block_value_was_calculated(&self) -> bool {
 		self.block_value().is_some()
 	}
@@ -220,11 +498,28 @@ impl PixlzrBlock {
 			PixlzrBlock::Raw(raw) => raw.data.data.as_slice(),
 		}
 	}
+	/// Sets the block value, dropping any horizontal and vertical values.
 	pub fn set_block_value(&mut self, value: f32) {
 		match self {
 			#[cfg(feature = "image-rs")]
-			PixlzrBlock::Image(image) => image.block_value = Some(value),
-			PixlzrBlock::Raw(raw) => raw.block_value = Some(value),
+			PixlzrBlock::Image(image) => {
+				image.block_value = Some(value);
+				image.axis_values = None;
+			}
+			PixlzrBlock::Raw(raw) => {
+				raw.block_value = Some(value);
+				raw.axis_values = None;
+			}
+		}
+	}
+	/// Sets the horizontal and vertical values, and the block value as
+	/// their combination.
+	pub fn set_axis_values(&mut self, values: (f32, f32)) {
+		self.set_block_value(values.0.hypot(values.1));
+		match self {
+			#[cfg(feature = "image-rs")]
+			PixlzrBlock::Image(image) => image.axis_values = Some(values),
+			PixlzrBlock::Raw(raw) => raw.axis_values = Some(values),
 		}
 	}
 }
@@ -257,8 +552,9 @@ impl PixlzrBlock {
 }
 
 impl PixlzrBlock {
-	pub fn pixels(&self) -> ChunksExact<u8> {
-		let chunk_size = 3 + self.has_alpha() as usize;
+	/// Iterates over the octets of each pixel, in the native endianness
+	pub fn pixels(&self) -> ChunksExact<'_, u8> {
+		let chunk_size = self.color().bytes_per_pixel();
 		match self {
 			#[cfg(feature = "image-rs")]
 			PixlzrBlock::Image(image) => {
@@ -291,11 +587,18 @@ impl PixlzrBlock {
 
 		use fast_image_resize::{images::Image, ResizeOptions};
 
-		let alpha = self.has_alpha();
-		let pixel_type = if alpha {
-			FIR_PixelType::U8x4
-		} else {
-			FIR_PixelType::U8x3
+		let color = self.color();
+		let pixel_type = match color {
+			ColorType::L8 => FIR_PixelType::U8,
+			ColorType::La8 => FIR_PixelType::U8x2,
+			ColorType::Rgb8 => FIR_PixelType::U8x3,
+			ColorType::Rgba8 => FIR_PixelType::U8x4,
+			ColorType::L16 => FIR_PixelType::U16,
+			ColorType::La16 => FIR_PixelType::U16x2,
+			ColorType::Rgb16 => FIR_PixelType::U16x3,
+			ColorType::Rgba16 => FIR_PixelType::U16x4,
+			ColorType::Rgb32F => FIR_PixelType::F32x3,
+			ColorType::Rgba32F => FIR_PixelType::F32x4,
 		};
 
 		let mut dst_image = Image::new(width, height, pixel_type);
@@ -305,17 +608,15 @@ impl PixlzrBlock {
 			2,
 		);
 
+		// Copies the pixels into a buffer aligned to the samples
+		let mut src_image =
+			Image::new(self.width(), self.height(), pixel_type);
+		src_image.buffer_mut().copy_from_slice(self.as_slice());
+
 		let mut resizer = Resizer::new();
-		let mut bytes = self.as_slice().to_vec();
 		resizer
 			.resize(
-				&Image::from_slice_u8(
-					self.width(),
-					self.height(),
-					&mut bytes,
-					pixel_type,
-				)
-				.unwrap(),
+				&src_image,
 				&mut dst_image,
 				&ResizeOptions::new().resize_alg(resize_alg),
 			)
@@ -325,8 +626,9 @@ impl PixlzrBlock {
 			width,
 			height,
 			block_value: None,
+			axis_values: None,
 			data: RawImage {
-				alpha,
+				color,
 				data: dst_image.into_vec(),
 			},
 		}
@@ -337,8 +639,8 @@ impl PixlzrBlock {
 pub mod tests_on_pixlzrblock {
 	#[allow(unused_imports)]
 	use super::{
-		I_FilterType, P_FilterType, PixlzrBlock, PixlzrBlockImage,
-		PixlzrBlockRaw, RawImage,
+		ColorType, I_FilterType, P_FilterType, PixlzrBlock,
+		PixlzrBlockImage, PixlzrBlockRaw, RawImage,
 	};
 	#[allow(unused_imports)]
 	use image::RgbaImage;
@@ -350,8 +652,9 @@ pub mod tests_on_pixlzrblock {
 			width: 100,
 			height: 100,
 			block_value: None,
+			axis_values: None,
 			data: RawImage {
-				alpha: true,
+				color: ColorType::Rgba8,
 				data: vec![0; 100 * 100 * 4],
 			},
 		});
@@ -364,6 +667,7 @@ pub mod tests_on_pixlzrblock {
 				width: 100,
 				height: 100,
 				block_value: None,
+				axis_values: None,
 				data: image::DynamicImage::ImageRgba8(
 					image::RgbaImage::from_raw(
 						100,
@@ -385,8 +689,9 @@ pub mod tests_on_pixlzrblock {
 			width: 100,
 			height: 100,
 			block_value: None,
+			axis_values: None,
 			data: RawImage {
-				alpha: true,
+				color: ColorType::Rgba8,
 				data: data.clone(),
 			},
 		});
@@ -404,8 +709,9 @@ pub mod tests_on_pixlzrblock {
 			width: 100,
 			height: 100,
 			block_value: None,
+			axis_values: None,
 			data: RawImage {
-				alpha: false,
+				color: ColorType::Rgb8,
 				data: vec![0; 100 * 100 * 3],
 			},
 		});
@@ -421,8 +727,9 @@ pub mod tests_on_pixlzrblock {
 			width: 100,
 			height: 100,
 			block_value: None,
+			axis_values: None,
 			data: RawImage {
-				alpha: false,
+				color: ColorType::Rgb8,
 				data: vec![255; 100 * 100 * 3],
 			},
 		});
@@ -433,4 +740,88 @@ pub mod tests_on_pixlzrblock {
 		assert_eq!(data.len(), 10 * 10 * 3);
 		assert_eq!(data, &vec![255; 10 * 10 * 3][..]);
 	}
+
+	#[test]
+	fn test_gray_blocks() {
+		let gray = image::GrayImage::from_fn(20, 10, |x, _| {
+			image::Luma([(x * 12) as u8])
+		});
+		let block = PixlzrBlock::Image(PixlzrBlockImage {
+			width: 20,
+			height: 10,
+			block_value: None,
+			axis_values: None,
+			data: gray.clone().into(),
+		});
+		assert_eq!(block.color(), ColorType::L8);
+		assert_eq!(block.pixels().len(), 20 * 10);
+
+		// Stored with a single channel
+		let raw = PixlzrBlockRaw::from(block.clone());
+		assert_eq!(raw.data.color, ColorType::L8);
+		assert_eq!(raw.data.data, gray.as_raw().as_slice());
+		let image = PixlzrBlockImage::from(PixlzrBlock::Raw(raw));
+		assert_eq!(image.data.as_luma8(), Some(&gray));
+
+		let resized = block.resize(5, 5, P_FilterType::Triangle);
+		assert_eq!(resized.color(), ColorType::L8);
+		assert_eq!(resized.as_slice().len(), 5 * 5);
+
+		let gray_alpha = PixlzrBlock::Image(PixlzrBlockImage {
+			width: 20,
+			height: 10,
+			block_value: None,
+			axis_values: None,
+			data: image::DynamicImage::ImageLumaA8(
+				image::GrayAlphaImage::new(20, 10),
+			),
+		});
+		let resized = gray_alpha.resize(4, 4, P_FilterType::Nearest);
+		assert_eq!(resized.color(), ColorType::La8);
+		assert_eq!(resized.as_slice().len(), 4 * 4 * 2);
+	}
+
+	#[test]
+	fn test_wide_blocks() {
+		// 16 bits per channel are kept
+		let gray = image::ImageBuffer::from_fn(20, 10, |x, y| {
+			image::Luma([(x * 3000 + y) as u16])
+		});
+		let block = PixlzrBlock::Image(PixlzrBlockImage {
+			width: 20,
+			height: 10,
+			block_value: None,
+			axis_values: None,
+			data: gray.clone().into(),
+		});
+		let raw = PixlzrBlockRaw::from(block.clone());
+		assert_eq!(raw.data.color, ColorType::L16);
+		assert_eq!(raw.data.data.len(), 20 * 10 * 2);
+		let image = PixlzrBlockImage::from(PixlzrBlock::Raw(raw));
+		assert_eq!(image.data.as_luma16(), Some(&gray));
+
+		let resized = block.resize(5, 5, P_FilterType::Triangle);
+		assert_eq!(resized.color(), ColorType::L16);
+		assert_eq!(resized.as_slice().len(), 5 * 5 * 2);
+
+		// And so are floats
+		let hdr = image::Rgba32FImage::from_pixel(
+			8,
+			8,
+			image::Rgba([4.5, 0.25, 1e-3, 1.]),
+		);
+		let block = PixlzrBlock::Image(PixlzrBlockImage {
+			width: 8,
+			height: 8,
+			block_value: None,
+			axis_values: None,
+			data: hdr.into(),
+		});
+		assert_eq!(block.color(), ColorType::Rgba32F);
+		let resized = block.resize(2, 2, P_FilterType::Lanczos3);
+		assert_eq!(resized.color(), ColorType::Rgba32F);
+		let image = PixlzrBlockImage::from(resized).data;
+		let pixel = image.as_rgba32f().unwrap().get_pixel(1, 1);
+		assert!((pixel[0] - 4.5).abs() < 1e-3);
+	}
 }
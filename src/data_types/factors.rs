@@ -0,0 +1,144 @@
+use super::{block::PixlzrBlock, pixlzr::Pixlzr, FilterType};
+use crate::{
+	analyzer::BlockAnalyzer,
+	error::{PixlzrError, Result},
+	policy::ShrinkPolicy,
+};
+
+impl Pixlzr {
+	/// Shrinks each block not shrunk yet, as `shrink_with` does, with its
+	/// detail value multiplied by its own factor, instead of a global one.
+	///
+	/// The `factors` are given per cell of the block grid, line by line:
+	/// the blocks of a tree layout take the factor of their cell.
+	///
+	/// Fails with `PixlzrError::FactorsMismatch` if there isn't one factor
+	/// per cell of `block_grid_dimensions()`.
+	pub fn shrink_with_factors<A, P>(
+		&mut self,
+		filter_downscale: FilterType,
+		analyzer: &A,
+		factors: &[f32],
+		policy: &P,
+	) -> Result<()>
+	where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+	{
+		let (width, height) = self.block_grid_dimensions();
+		if factors.len() != (width * height) as usize {
+			return Err(PixlzrError::FactorsMismatch {
+				expected: (width, height),
+				found: factors.len(),
+			});
+		}
+		self.shrink_with_factor_fn(
+			filter_downscale,
+			analyzer,
+			|col, row, _| factors[(row * width + col) as usize],
+			policy,
+		);
+		Ok(())
+	}
+
+	/// Shrinks each block not shrunk yet, as `shrink_with` does, with its
+	/// detail value multiplied by the factor `factor` gives it, from the
+	/// (column, row) of its cell in the block grid, and the block.
+	pub fn shrink_with_factor_fn<A, P, F>(
+		&mut self,
+		filter_downscale: FilterType,
+		analyzer: &A,
+		factor: F,
+		policy: &P,
+	) where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+		F: Fn(u32, u32, &PixlzrBlock) -> f32 + Sync,
+	{
+		let cells = self.block_cells();
+		self.shrink_each(
+			filter_downscale,
+			analyzer,
+			policy,
+			|index, block, value| {
+				let (col, row) = cells[index];
+				let factor = factor(col, row, block);
+				(value.0 * factor, value.1 * factor)
+			},
+		);
+	}
+
+	/// The (column, row) of the cell of the block grid holding each block
+	fn block_cells(&self) -> Vec<(u32, u32)> {
+		self.block_rects()
+			.into_iter()
+			.map(|rect| {
+				(rect.x / self.block_width, rect.y / self.block_height)
+			})
+			.collect()
+	}
+}
+
+pub mod tests_on_factors {
+	#[allow(unused_imports)]
+	use super::Pixlzr;
+	#[allow(unused_imports)]
+	use crate::data_types::test_images::noise;
+	#[allow(unused_imports)]
+	use crate::{
+		analyzer::OklabDeviation, data_types::FilterType,
+		error::PixlzrError, policy::PowerOfTwo,
+	};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, RgbImage};
+
+	#[allow(dead_code)]
+	fn sample_pixlzr() -> Pixlzr {
+		let img = RgbImage::from_fn(48, 32, |x, y| {
+			let noise = noise(x, y, 97);
+			image::Rgb([(x * 4 + noise) as u8, (y * 6) as u8, 60])
+		});
+		Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16)
+	}
+
+	#[test]
+	fn test_shrink_with_factors() {
+		// Shrinks the bottom line of blocks, and keeps the top one whole
+		let factors = [64., 64., 64., 0.01, 0.01, 0.01];
+		let mut pix = sample_pixlzr();
+		pix.shrink_with_factors(
+			FilterType::Nearest,
+			&OklabDeviation::default(),
+			&factors,
+			&PowerOfTwo,
+		)
+		.unwrap();
+		assert!(pix.blocks[..3]
+			.iter()
+			.all(|b| b.dimensions() == (16, 16)));
+		assert!(pix.blocks[3..].iter().all(|b| b.width() < 16));
+
+		// The same as a closure of the cell
+		let mut with = sample_pixlzr();
+		with.shrink_with_factor_fn(
+			FilterType::Nearest,
+			&OklabDeviation::default(),
+			|_, row, _| if row == 0 { 64. } else { 0.01 },
+			&PowerOfTwo,
+		);
+		assert_eq!(pix.block_scale_levels(), with.block_scale_levels());
+
+		assert!(matches!(
+			sample_pixlzr().shrink_with_factors(
+				FilterType::Nearest,
+				&OklabDeviation::default(),
+				&[1.; 4],
+				&PowerOfTwo,
+			),
+			Err(PixlzrError::FactorsMismatch {
+				expected: (3, 2),
+				found: 4
+			})
+		));
+	}
+}
@@ -0,0 +1,262 @@
+use super::pixlzr::Pixlzr;
+use crate::encoding::codec::scale_code;
+
+use std::ops::Range;
+
+#[cfg(feature = "image-rs")]
+use super::{
+	block::{ColorType, PixlzrBlock},
+	Compression, Metadata,
+};
+#[cfg(feature = "image-rs")]
+use crate::{
+	error::{PixlzrError, Result},
+	operations::get_block_variance,
+	split::get_image_block,
+};
+#[cfg(feature = "image-rs")]
+use image::DynamicImage;
+
+/// A rectangle of the image, covered by a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockRect {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl BlockRect {
+	pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+		Self {
+			x,
+			y,
+			width,
+			height,
+		}
+	}
+	/// Returns the width and height of the rectangle as a tuple of u32.
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+	/// Splits the rectangle in its top-left, top-right, bottom-left and
+	/// bottom-right quarters, the first ones taking the odd pixels.
+	///
+	/// Returns `None` if a side is shorter than 2 pixels.
+	pub fn quadrants(&self) -> Option<[BlockRect; 4]> {
+		if self.width < 2 || self.height < 2 {
+			return None;
+		}
+		let (w0, h0) = (self.width.div_ceil(2), self.height.div_ceil(2));
+		let (w1, h1) = (self.width - w0, self.height - h0);
+		let (x1, y1) = (self.x + w0, self.y + h0);
+		Some([
+			BlockRect::new(self.x, self.y, w0, h0),
+			BlockRect::new(x1, self.y, w1, h0),
+			BlockRect::new(self.x, y1, w0, h1),
+			BlockRect::new(x1, y1, w1, h1),
+		])
+	}
+}
+
+/// How the blocks of a `Pixlzr` cover the image
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum BlockLayout {
+	/// A uniform grid of `block_width` x `block_height` blocks, line by line
+	#[default]
+	Grid,
+	/// A grid of `block_width` x `block_height` cells, each one split as a
+	/// quadtree.
+	///
+	/// Holds the rectangle of each block, cell by cell, line by line, and
+	/// in the depth-first order of each cell's tree.
+	Tree(Vec<BlockRect>),
+}
+
+impl Pixlzr {
+	/// Returns `true` if the blocks are a uniform grid.
+	pub fn is_grid(&self) -> bool {
+		self.layout == BlockLayout::Grid
+	}
+
+	/// The rectangle of the image covered by each block, in the order of
+	/// `blocks`.
+	pub fn block_rects(&self) -> Vec<BlockRect> {
+		match &self.layout {
+			BlockLayout::Grid => {
+				grid_rects(self.dimensions(), self.block_dimensions())
+			}
+			BlockLayout::Tree(rects) => rects.clone(),
+		}
+	}
+
+	/// The horizontal and vertical downscale levels of each block, in the
+	/// order of `blocks`: a block of level `n` is `2^n` times smaller than
+	/// its rectangle, along that axis.
+	///
+	/// `None` for a block whose dimensions aren't a power-of-two downscale
+	/// of its rectangle.
+	pub fn block_scale_levels(&self) -> Vec<Option<(u8, u8)>> {
+		self.blocks
+			.iter()
+			.zip(self.block_rects())
+			.map(|(block, rect)| {
+				scale_code(rect.dimensions(), block.dimensions())
+					.map(|code| (code >> 4, code & 0xf))
+			})
+			.collect()
+	}
+
+	/// The range of `blocks` on each line of the block grid, given their
+	/// `rects`
+	pub(crate) fn line_ranges(
+		&self,
+		rects: &[BlockRect],
+	) -> Vec<Range<usize>> {
+		let rects = &rects[..rects.len().min(self.blocks.len())];
+		let mut start = 0;
+		(0..self.block_grid_height())
+			.map(|row| {
+				let len = rects[start..]
+					.iter()
+					.take_while(|rect| rect.y / self.block_height == row)
+					.count();
+				start += len;
+				start - len..start
+			})
+			.collect()
+	}
+
+	#[cfg(feature = "image-rs")]
+	/// Splits the `image` into a grid of `max_block_size` cells, and each
+	/// cell as a quadtree: a block whose variance reaches `threshold` is
+	/// split in four, down to blocks of `min_block_size`.
+	///
+	/// Detailed regions get small blocks, and flat ones get large blocks.
+	///
+	/// Fails with `PixlzrError::Invalid` if a side of `max_block_size` is
+	/// zero.
+	pub fn from_image_tree(
+		image: &DynamicImage,
+		threshold: f32,
+		max_block_size: (u32, u32),
+		min_block_size: (u32, u32),
+	) -> Result<Pixlzr> {
+		// Images of other color types are converted
+		let color = ColorType::from_image_color(image.color());
+		let converted;
+		let image = if image.color() == color.into() {
+			image
+		} else {
+			converted = color.convert_image(image.clone());
+			&converted
+		};
+		let (block_width, block_height) = max_block_size;
+		if block_width == 0 || block_height == 0 {
+			return Err(PixlzrError::Invalid {
+				offset: 0,
+				reason:
+					"the cells of a tree must have positive dimensions",
+			});
+		}
+		let mut pix = Pixlzr {
+			width: image.width(),
+			height: image.height(),
+			block_width,
+			block_height,
+			filter: None,
+			compression: Compression::None,
+			metadata: Metadata::default(),
+			preview: false,
+			layout: BlockLayout::Grid,
+			blocks: vec![],
+		};
+		let mut rects = vec![];
+		for cell in grid_rects(pix.dimensions(), max_block_size) {
+			split_tree(
+				image,
+				cell,
+				threshold,
+				min_block_size,
+				&mut pix.blocks,
+				&mut rects,
+			);
+		}
+		pix.layout = BlockLayout::Tree(rects);
+		Ok(pix)
+	}
+}
+
+/// The rectangle of the cell at (`col`, `row`) of the block grid, over an
+/// image of `width` x `height` pixels
+pub(crate) fn grid_cell(
+	(width, height): (u32, u32),
+	(block_width, block_height): (u32, u32),
+	(col, row): (u32, u32),
+) -> BlockRect {
+	let (x, y) = (col * block_width, row * block_height);
+	BlockRect::new(
+		x,
+		y,
+		block_width.min(width - x),
+		block_height.min(height - y),
+	)
+}
+
+/// The rectangles of the blocks of a grid layout, line by line
+pub(crate) fn grid_rects(
+	dimensions: (u32, u32),
+	block_dimensions: (u32, u32),
+) -> Vec<BlockRect> {
+	let (width, height) = dimensions;
+	let (block_width, block_height) = block_dimensions;
+	(0..height.div_ceil(block_height))
+		.flat_map(|row| {
+			(0..width.div_ceil(block_width)).map(move |col| {
+				grid_cell(dimensions, block_dimensions, (col, row))
+			})
+		})
+		.collect()
+}
+
+#[cfg(feature = "image-rs")]
+/// Appends the blocks of the quadtree over `rect`, and their rectangles
+fn split_tree(
+	image: &DynamicImage,
+	rect: BlockRect,
+	threshold: f32,
+	(min_width, min_height): (u32, u32),
+	blocks: &mut Vec<PixlzrBlock>,
+	rects: &mut Vec<BlockRect>,
+) {
+	let before_average: fn(f32, f32) -> f32 =
+		|x: f32, avg: f32| (x - avg).abs();
+	let after_average: fn(f32) -> f32 = |x: f32| x;
+
+	let block =
+		get_image_block(image, rect.x, rect.y, rect.width, rect.height);
+	let quadrants = rect.quadrants().filter(|_| {
+		rect.width >= min_width.max(1).saturating_mul(2)
+			&& rect.height >= min_height.max(1).saturating_mul(2)
+			&& get_block_variance(&block, &before_average, &after_average)
+				>= threshold
+	});
+	match quadrants {
+		Some(quadrants) => {
+			for quadrant in quadrants {
+				split_tree(
+					image,
+					quadrant,
+					threshold,
+					(min_width, min_height),
+					blocks,
+					rects,
+				);
+			}
+		}
+		None => {
+			blocks.push(block);
+			rects.push(rect);
+		}
+	}
+}
@@ -0,0 +1,177 @@
+use super::{pixlzr::Pixlzr, FilterType};
+use crate::{
+	analyzer::BlockAnalyzer,
+	error::{PixlzrError, Result},
+	operations::reduce_image_section,
+	policy::ShrinkPolicy,
+};
+
+use image::{DynamicImage, GrayImage, Luma};
+use rayon::iter::{
+	IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+
+impl Pixlzr {
+	/// Shrinks each block not shrunk yet, as `shrink_with` does, with its
+	/// detail value scaled by the importance the `mask` gives to it.
+	///
+	/// The weight of a block is the average of the mask over its
+	/// rectangle, from `0` (black) to `1` (white), and lifts the scale the
+	/// `policy` gives it towards `1`, as `scale + weight * (1 - scale)`:
+	/// black regions shrink freely, and white ones are never shrunk,
+	/// whatever the sign of `factor`.
+	///
+	/// Fails with `PixlzrError::DimensionsMismatch` if the mask doesn't
+	/// have the dimensions of the image.
+	pub fn shrink_with_mask<A, P>(
+		&mut self,
+		filter_downscale: FilterType,
+		mask: &GrayImage,
+		analyzer: &A,
+		factor: f32,
+		policy: &P,
+	) -> Result<()>
+	where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+	{
+		let weights = self.mask_weights(mask)?;
+		self.blocks = self
+			.blocks
+			.par_iter()
+			.zip(weights.par_iter())
+			.map(|(block, &weight)| {
+				if block.block_value().is_some() {
+					return block.clone();
+				}
+				let masked = |value: f32| {
+					let scale = policy.scale(value);
+					scale + weight * (1. - scale)
+				};
+				let (hz, vr) = analyzer.analyze(block);
+				reduce_image_section(
+					(hz * factor, vr * factor),
+					block,
+					filter_downscale,
+					&masked,
+				)
+			})
+			.collect();
+		Ok(())
+	}
+
+	/// The average weight, in `[0; 1]`, of the `mask` over each block
+	fn mask_weights(&self, mask: &GrayImage) -> Result<Vec<f32>> {
+		let (expected, found) = (self.dimensions(), mask.dimensions());
+		if expected != found {
+			return Err(PixlzrError::DimensionsMismatch {
+				expected,
+				found,
+			});
+		}
+		Ok(self
+			.block_rects()
+			.into_iter()
+			.map(|rect| {
+				let mut sum = 0u64;
+				for y in rect.y..rect.y + rect.height {
+					for x in rect.x..rect.x + rect.width {
+						sum += mask.get_pixel(x, y).0[0] as u64;
+					}
+				}
+				let count =
+					(rect.width as u64 * rect.height as u64).max(1);
+				sum as f32 / (count * u8::MAX as u64) as f32
+			})
+			.collect())
+	}
+}
+
+/// The alpha channel of the `image` as a mask: opaque pixels are white,
+/// and transparent ones black.
+///
+/// Images without alpha are fully opaque.
+pub fn alpha_mask(image: &DynamicImage) -> GrayImage {
+	let image = image.to_luma_alpha8();
+	GrayImage::from_fn(image.width(), image.height(), |x, y| {
+		Luma([image.get_pixel(x, y).0[1]])
+	})
+}
+
+pub mod tests_on_mask {
+	#[allow(unused_imports)]
+	use super::{alpha_mask, Pixlzr};
+	#[allow(unused_imports)]
+	use crate::data_types::test_images::noise;
+	#[allow(unused_imports)]
+	use crate::{
+		analyzer::OklabDeviation, data_types::FilterType,
+		error::PixlzrError, policy::PowerOfTwo,
+	};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, GrayImage, RgbaImage};
+
+	#[allow(dead_code)]
+	fn sample_image() -> DynamicImage {
+		DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 16, |x, y| {
+			let noise = noise(x, y, 97) as u8;
+			// The left half is opaque, and the right one transparent
+			image::Rgba([
+				noise,
+				(y * 8) as u8,
+				90,
+				if x < 16 { 255 } else { 0 },
+			])
+		}))
+	}
+
+	#[test]
+	fn test_shrink_with_mask() {
+		let img = sample_image();
+		let mut pix = Pixlzr::from_image(&img, 16, 16);
+		let mask = alpha_mask(&img);
+		assert_eq!(mask.get_pixel(0, 0).0, [255]);
+		assert_eq!(mask.get_pixel(31, 0).0, [0]);
+
+		pix.shrink_with_mask(
+			FilterType::Nearest,
+			&mask,
+			&OklabDeviation::default(),
+			0.01,
+			&PowerOfTwo,
+		)
+		.unwrap();
+		// The white block is kept whole, and the black one shrunk
+		assert_eq!(pix.blocks[0].dimensions(), (16, 16));
+		assert!(pix.blocks[1].width() < 16);
+
+		// Whatever the sign of the factor
+		let mut negative = Pixlzr::from_image(&img, 16, 16);
+		negative
+			.shrink_with_mask(
+				FilterType::Nearest,
+				&mask,
+				&OklabDeviation::default(),
+				-0.5,
+				&PowerOfTwo,
+			)
+			.unwrap();
+		assert_eq!(negative.blocks[0].dimensions(), (16, 16));
+		assert!(negative.blocks[1].width() < 16);
+		assert!(negative
+			.blocks
+			.iter()
+			.all(|b| b.block_value().is_some_and(f32::is_finite)));
+
+		assert!(matches!(
+			pix.shrink_with_mask(
+				FilterType::Nearest,
+				&GrayImage::new(8, 8),
+				&OklabDeviation::default(),
+				1.,
+				&PowerOfTwo,
+			),
+			Err(PixlzrError::DimensionsMismatch { .. })
+		));
+	}
+}
@@ -0,0 +1,73 @@
+#[cfg(feature = "image-rs")]
+use image::{error::ImageResult, ImageDecoder, ImageEncoder};
+
+/// The metadata carried along with a PIXLZR image.
+///
+/// It's written in the chunk section of the header, and is kept as is by
+/// the operations over the image.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metadata {
+	/// The ICC color profile
+	pub icc: Option<Vec<u8>>,
+	/// The raw EXIF data, starting with the TIFF header
+	pub exif: Option<Vec<u8>>,
+	/// The XMP packet, as UTF-8 XML
+	pub xmp: Option<Vec<u8>>,
+	/// Free-form key/value pairs, in the order they're written
+	pub text: Vec<(String, String)>,
+}
+
+impl Metadata {
+	/// Returns `true` if there is no metadata at all.
+	pub fn is_empty(&self) -> bool {
+		self.icc.is_none()
+			&& self.exif.is_none()
+			&& self.xmp.is_none()
+			&& self.text.is_empty()
+	}
+
+	/// The value of the first text pair with the given `key`.
+	pub fn get_text(&self, key: &str) -> Option<&str> {
+		self.text
+			.iter()
+			.find(|(k, _)| k == key)
+			.map(|(_, value)| value.as_str())
+	}
+
+	/// Appends a text pair.
+	pub fn push_text(
+		&mut self,
+		key: impl Into<String>,
+		value: impl Into<String>,
+	) {
+		self.text.push((key.into(), value.into()));
+	}
+
+	#[cfg(feature = "image-rs")]
+	/// Reads the ICC profile, EXIF and XMP data known by `decoder`.
+	///
+	/// Must be called before the image is decoded.
+	pub fn from_decoder(
+		decoder: &mut impl ImageDecoder,
+	) -> ImageResult<Self> {
+		Ok(Self {
+			icc: decoder.icc_profile()?,
+			exif: decoder.exif_metadata()?,
+			xmp: decoder.xmp_metadata()?,
+			text: vec![],
+		})
+	}
+
+	#[cfg(feature = "image-rs")]
+	/// Hands the ICC profile and EXIF data over to `encoder`.
+	///
+	/// The ones the encoder doesn't support are silently dropped.
+	pub fn apply_to_encoder(&self, encoder: &mut impl ImageEncoder) {
+		if let Some(icc) = &self.icc {
+			let _ = encoder.set_icc_profile(icc.clone());
+		}
+		if let Some(exif) = &self.exif {
+			let _ = encoder.set_exif_metadata(exif.clone());
+		}
+	}
+}
@@ -1,11 +1,37 @@
 pub(crate) mod block;
+pub(crate) mod factors;
 #[cfg(feature = "image-rs")]
 pub(crate) mod iter;
+pub(crate) mod layout;
+#[cfg(feature = "image-rs")]
+pub(crate) mod mask;
+pub(crate) mod metadata;
 pub(crate) mod pixlzr;
 #[cfg(feature = "image-rs")]
 pub(crate) mod pixlzr_image;
+pub(crate) mod preview;
 pub mod semver;
-pub use self::{block::*, iter::*, pixlzr::*, semver::Semver};
+pub(crate) mod sequence;
+#[cfg(feature = "image-rs")]
+pub(crate) mod sequence_image;
+#[cfg(feature = "image-rs")]
+pub(crate) mod target;
+pub(crate) mod test_images;
+pub use self::{
+	block::*,
+	iter::*,
+	layout::{BlockLayout, BlockRect},
+	metadata::Metadata,
+	pixlzr::*,
+	preview::Preview,
+	semver::Semver,
+	sequence::*,
+};
+#[cfg(feature = "image-rs")]
+pub use self::{
+	mask::alpha_mask,
+	target::{Target, TargetResult},
+};
 
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Default)]
@@ -119,3 +145,40 @@ impl From<u8> for FilterType {
 		}
 	}
 }
+
+/// The second stage compression, applied over each line of encoded blocks.
+///
+/// Each variant but `None` carries its compression level.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum Compression {
+	#[default]
+	None,
+	/// Deflate, with a level from 0 to 9
+	Deflate(u32),
+	/// Zstandard, with a level from 1 to 22
+	Zstd(i32),
+}
+
+impl Compression {
+	pub const DEFAULT_DEFLATE_LEVEL: u32 = 6;
+	pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+
+	/// The compression method, as written in the header
+	pub fn as_u8(self) -> u8 {
+		match self {
+			Compression::None => 0,
+			Compression::Deflate(_) => 1,
+			Compression::Zstd(_) => 2,
+		}
+	}
+	/// The compression method written in the header, with its default
+	/// level
+	pub fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0 => Some(Compression::None),
+			1 => Some(Compression::Deflate(Self::DEFAULT_DEFLATE_LEVEL)),
+			2 => Some(Compression::Zstd(Self::DEFAULT_ZSTD_LEVEL)),
+			_ => None,
+		}
+	}
+}
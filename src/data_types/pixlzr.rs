@@ -1,19 +1,21 @@
-use super::{block::*, FilterType as P_FilterType};
-
-use crate::operations::{
-	get_block_variance, get_block_variance_directionally,
-	reduce_image_section,
+use super::{
+	block::*, BlockLayout, Compression, FilterType as P_FilterType,
+	Metadata,
 };
 
-use std::iter::Iterator;
+use crate::{
+	analyzer::{BlockAnalyzer, OklabDeviation, Sobel},
+	operations::reduce_image_section,
+	policy::{PowerOfTwo, ShrinkPolicy},
+};
 
 use rayon::{
-	iter::{IndexedParallelIterator, ParallelIterator},
+	iter::{
+		IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator,
+	},
 	slice::ParallelSlice,
 };
 
-const BASE_FACTOR: f32 = 10.0;
-
 #[derive(Clone)]
 pub struct Pixlzr {
 	pub width: u32,
@@ -21,6 +23,15 @@ pub struct Pixlzr {
 	pub block_width: u32,
 	pub block_height: u32,
 	pub filter: Option<P_FilterType>,
+	/// The second stage compression, used when encoding
+	pub compression: Compression,
+	/// The ICC profile, EXIF, XMP and text metadata
+	pub metadata: Metadata,
+	/// Whether to embed a preview, of one average color per block, when
+	/// encoding
+	pub preview: bool,
+	/// How the blocks cover the image
+	pub layout: BlockLayout,
 	pub blocks: Vec<PixlzrBlock>,
 }
 
@@ -35,11 +46,11 @@ impl Pixlzr {
 	}
 	#[inline]
 	pub fn block_grid_width(&self) -> u32 {
-		(self.width as f32 / self.block_width as f32).ceil() as u32
+		self.width.div_ceil(self.block_width)
 	}
 	#[inline]
 	pub fn block_grid_height(&self) -> u32 {
-		(self.height as f32 / self.block_height as f32).ceil() as u32
+		self.height.div_ceil(self.block_height)
 	}
 	pub fn block_grid_dimensions(&self) -> (u32, u32) {
 		(self.block_grid_width(), self.block_grid_height())
@@ -50,73 +61,55 @@ impl Pixlzr {
 	/// A trailing block is a block that is below the full size of (`block_width`, `block_height`).
 	pub fn block_grid_has_trailing(&self) -> (bool, bool) {
 		(
-			self.width % self.block_width > 0,
-			self.height % self.block_height > 0,
+			!self.width.is_multiple_of(self.block_width),
+			!self.height.is_multiple_of(self.block_height),
 		)
 	}
 
 	/// Returns a parallel iterator over the image's blocks organized in lines, with the amount of lines equal to the vertical size of the block grid.
 	///
+	/// Only meaningful for the grid layout.
+	///
 	/// Each element of the iterator is a slice of blocks, with the length equal to the horizontal size of the block grid.
 	///
 	/// Like `lines`, but parallel through `rayon`.
-	pub fn par_lines(&self) -> rayon::slice::ChunksExact<PixlzrBlock> {
+	pub fn par_lines(&self) -> rayon::slice::ChunksExact<'_, PixlzrBlock> {
 		self.blocks
 			.par_chunks_exact(self.block_grid_width() as usize)
 	}
 
 	/// Returns an iterator over the image's blocks organized in lines, with the amount of lines equal to the vertical size of the block grid.
 	///
+	/// Only meaningful for the grid layout.
+	///
 	/// Each element of the iterator is a slice of blocks, with the length equal to the horizontal size of the block grid.
 	///
 	/// Like `par_lines`, but not parallel.
-	pub fn lines(&self) -> std::slice::ChunksExact<PixlzrBlock> {
+	pub fn lines(&self) -> std::slice::ChunksExact<'_, PixlzrBlock> {
 		self.blocks.chunks_exact(self.block_grid_width() as usize)
 	}
 
+	/// Resizes every block back to the size of the rectangle it covers.
 	pub fn expand(&self, filter: P_FilterType) -> Self {
-		// Extract properties
-		let (width, height) = self.dimensions();
-		let (block_width, block_height) = self.block_dimensions();
-		let (cols, rows) = self.block_grid_dimensions();
-
-		let trailing_horizontal = width % block_width;
-		let trailing_vertical = height % block_height;
-		let has_trailing = self.block_grid_has_trailing();
-
-		// Create list of blocks to be returned
 		let blocks: Vec<PixlzrBlock> = self
-			.par_lines()
-			.zip(0..rows)
-			.flat_map(|(line, y)| {
-				let nheight = if y == rows - 1 && has_trailing.1 {
-					trailing_vertical
-				} else {
-					block_height
-				};
-
-				// For each block
-				line.iter()
-					.zip(0..cols)
-					.map(|(block, x)| {
-						// Reduce the cumulatime limit
-						let nwidth = if x == cols - 1 && has_trailing.0 {
-							trailing_horizontal
-						} else {
-							block_width
-						};
-						block.resize(nwidth, nheight, filter)
-					})
-					.collect::<Vec<PixlzrBlock>>()
+			.blocks
+			.par_iter()
+			.zip(self.block_rects())
+			.map(|(block, rect)| {
+				block.resize(rect.width, rect.height, filter)
 			})
 			.collect();
 
 		Self {
 			width: self.width,
 			height: self.height,
-			block_width,
-			block_height,
+			block_width: self.block_width,
+			block_height: self.block_height,
 			filter: Some(filter),
+			compression: self.compression,
+			metadata: self.metadata.clone(),
+			preview: self.preview,
+			layout: self.layout.clone(),
 			blocks,
 		}
 	}
@@ -127,78 +120,107 @@ impl Pixlzr {
 		before_average: &fn(f32, f32) -> f32,
 		after_average: &fn(f32) -> f32,
 	) {
-		self.blocks = self
-			.blocks
-			.iter()
-			.map({
-				|block| {
-					if block.block_value().is_some() {
-						return (*block).clone();
-					}
-					// Calculate the value
-					let value = get_block_variance(
-						&block,
-						&before_average,
-						&after_average,
-					);
-					reduce_image_section(
-						(value, value),
-						block,
-						filter_downscale,
-					)
-				}
-			})
-			.collect();
+		let analyzer = OklabDeviation {
+			before: *before_average,
+			after: *after_average,
+		};
+		self.shrink_with(filter_downscale, &analyzer, 1., &PowerOfTwo);
 	}
 
+	/// Shrinks each block not shrunk yet, by its Oklab deviation times
+	/// `factor`, to the scale the `policy` gives it.
 	#[inline]
-	pub fn shrink_by(
+	pub fn shrink_by<P>(
 		&mut self,
 		filter_downscale: P_FilterType,
 		factor: f32,
-	) {
-		let before_average: fn(f32, f32) -> f32 =
-			|x: f32, avg: f32| (x - avg).abs();
-		let after_average = |x: f32| x * factor * BASE_FACTOR;
+		policy: &P,
+	) where
+		P: ShrinkPolicy + ?Sized,
+	{
+		self.shrink_with(
+			filter_downscale,
+			&OklabDeviation::default(),
+			factor,
+			policy,
+		);
+	}
+
+	/// Shrinks every block, even the ones shrunk already, by its
+	/// horizontal and vertical gradients times `factor`, to the scales the
+	/// `policy` gives them.
+	pub fn shrink_directionally<P>(
+		&mut self,
+		filter_downscale: P_FilterType,
+		factor: f32,
+		policy: &P,
+	) where
+		P: ShrinkPolicy + ?Sized,
+	{
 		self.blocks = self
 			.blocks
-			.iter()
-			.map({
-				|block| {
-					if block.block_value().is_some() {
-						return (*block).clone();
-					}
-					// Calculate the value
-					let value = get_block_variance(
-						&block,
-						&before_average,
-						&after_average,
-					);
-					reduce_image_section(
-						(value, value),
-						block,
-						filter_downscale,
-					)
-				}
+			.par_iter()
+			.map(|block| {
+				let (hz, vr) = Sobel.analyze(block);
+				reduce_image_section(
+					(hz * factor, vr * factor),
+					block,
+					filter_downscale,
+					policy,
+				)
 			})
 			.collect();
 	}
 
-	pub fn shrink_directionally(
+	/// Shrinks each block not shrunk yet, by the detail the `analyzer`
+	/// measures on it times `factor`, to the scales the `policy` gives it.
+	pub fn shrink_with<A, P>(
 		&mut self,
 		filter_downscale: P_FilterType,
+		analyzer: &A,
 		factor: f32,
-	) {
+		policy: &P,
+	) where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+	{
+		self.shrink_each(
+			filter_downscale,
+			analyzer,
+			policy,
+			|_, _, value| (value.0 * factor, value.1 * factor),
+		);
+	}
+
+	/// Shrinks each block not shrunk yet, by the detail the `analyzer`
+	/// measures on it, as `adjust` changes it given the block and its
+	/// index, to the scales the `policy` gives it.
+	pub(crate) fn shrink_each<A, P, F>(
+		&mut self,
+		filter_downscale: P_FilterType,
+		analyzer: &A,
+		policy: &P,
+		adjust: F,
+	) where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+		F: Fn(usize, &PixlzrBlock, (f32, f32)) -> (f32, f32) + Sync,
+	{
 		self.blocks = self
 			.blocks
-			.iter()
-			.map(|block| {
+			.par_iter()
+			.enumerate()
+			.map(|(index, block)| {
+				if block.block_value().is_some() {
+					return block.clone();
+				}
 				// Calculate the value
-				let value = get_block_variance_directionally(block);
+				let value = adjust(index, block, analyzer.analyze(block));
 				reduce_image_section(
-					(value.0 * factor, value.1 * factor),
+					value,
 					block,
 					filter_downscale,
+					policy,
 				)
 			})
 			.collect();
@@ -1,6 +1,12 @@
-use super::{block::*, iter::*, pixlzr::Pixlzr, FilterType};
+use super::{
+	block::*, iter::*, pixlzr::Pixlzr, BlockLayout, Compression,
+	FilterType, Metadata,
+};
 
-use image::{DynamicImage, GenericImage};
+use image::{imageops::replace, DynamicImage};
+use rayon::iter::{
+	IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
 
 impl Pixlzr {
 	pub fn from_image(
@@ -8,6 +14,15 @@ impl Pixlzr {
 		block_width: u32,
 		block_height: u32,
 	) -> Pixlzr {
+		// Images of other color types are converted
+		let color = ColorType::from_image_color(image.color());
+		let converted;
+		let image = if image.color() == color.into() {
+			image
+		} else {
+			converted = color.convert_image(image.clone());
+			&converted
+		};
 		let blocks: Vec<_> =
 			ImageBlockIterator::new(image, block_width, block_height)
 				.collect();
@@ -17,65 +32,166 @@ impl Pixlzr {
 			block_width,
 			block_height,
 			filter: None,
+			compression: Compression::None,
+			metadata: Metadata::default(),
+			preview: false,
+			layout: BlockLayout::Grid,
 			blocks,
 		}
 	}
 
 	pub fn to_image(&self, filter: FilterType) -> DynamicImage {
-		// println!("Pre-expansion");
 		let pix = self.expand(filter);
-		// println!("Post-expansion");
-		let mut output =
-			if pix.blocks.iter().any(|block| block.has_alpha()) {
-				DynamicImage::new_rgba8(self.width, self.height)
-			} else {
-				DynamicImage::new_rgb8(self.width, self.height)
-			};
-		let (block_width, block_height) = pix.block_dimensions();
-		// let cols = (self.width as f32 / block_width as f32).ceil() as u32;
-		// let mut x = 0;
-		// let mut y = 0;
-		// println!(
-		// 	"b wh: {:?}, out wh: {:?}",
-		// 	pix.block_dimensions(),
-		// 	pix.dimensions()
-		// );
-		pix.lines().enumerate().for_each(|(y, line)| {
-			line.iter().enumerate().for_each(|(x, block)| {
-				let img = PixlzrBlockImage::from(block.clone()).data;
-				output
-					.copy_from(
-						&img,
-						x as u32 * block_width,
-						y as u32 * block_height,
-					)
-					.unwrap();
+		let blocks = pix
+			.blocks
+			.into_iter()
+			.zip(self.block_rects())
+			.map(|(block, rect)| (block, rect.x, rect.y));
+		self.compose(blocks, self.width, self.height)
+	}
+
+	/// Renders the image at `width` x `height` pixels, resizing each block
+	/// straight from its stored size to its share of the output.
+	///
+	/// Unlike `to_image`, the full resolution image is never created.
+	pub fn to_image_scaled(
+		&self,
+		width: u32,
+		height: u32,
+		filter: FilterType,
+	) -> DynamicImage {
+		// The position in the output of the `pos` pixel, over a side of
+		// `size` pixels scaled into `target` pixels
+		let scale = |pos: u32, size: u32, target: u32| {
+			(pos as u64 * target as u64 / size.max(1) as u64) as u32
+		};
+		let blocks: Vec<_> = self
+			.blocks
+			.par_iter()
+			.zip(self.block_rects())
+			.filter_map(|(block, rect)| {
+				let (x0, x1) = (
+					scale(rect.x, self.width, width),
+					scale(rect.x + rect.width, self.width, width),
+				);
+				let (y0, y1) = (
+					scale(rect.y, self.height, height),
+					scale(rect.y + rect.height, self.height, height),
+				);
+				// Blocks narrower than an output pixel are left out
+				(x1 > x0 && y1 > y0).then(|| {
+					(block.resize(x1 - x0, y1 - y0, filter), x0, y0)
+				})
 			})
-		});
-		// for block in pix.blocks {
-		// 	let img = &block.as_image().unwrap().data;
-		// 	// println!(
-		// 	// 	"xy: ({x}, {y}),\t{:?} => ({}, {})\tim wh: {:?}",
-		// 	// 	(x * block_width, y * block_height),
-		// 	// 	x * block_width + img.width(),
-		// 	// 	y * block_height + img.height(),
-		// 	// 	img.dimensions(),
-		// 	// );
-		// 	output
-		// 		.copy_from(img, x * block_width, y * block_height)
-		// 		.unwrap();
-		// 	x += 1;
-		// 	if x == cols {
-		// 		x = 0;
-		// 		y += 1;
-		// 	}
-		// }
+			.collect();
+		self.compose(blocks, width, height)
+	}
+
+	/// Pastes each block at its (x, y) position, into an image of `width` x
+	/// `height` pixels
+	fn compose(
+		&self,
+		blocks: impl IntoIterator<Item = (PixlzrBlock, u32, u32)>,
+		width: u32,
+		height: u32,
+	) -> DynamicImage {
+		// Grayscale only if all blocks are, and as deep as the deepest block
+		let gray = self.blocks.iter().all(|block| block.color().is_gray());
+		let alpha = self.blocks.iter().any(|block| block.has_alpha());
+		let sample = self
+			.blocks
+			.iter()
+			.map(|block| block.color().sample_type())
+			.max()
+			.unwrap_or_default();
+		let color = ColorType::with(sample, gray, alpha);
+		let mut output = DynamicImage::new(width, height, color.into());
+		for (block, x, y) in blocks {
+			let img = PixlzrBlockImage::from(block).data;
+			paste(&mut output, color.convert_image(img), x, y);
+		}
 		output
 	}
 }
 
+/// Pastes the `block` into `output` at (`x`, `y`), keeping the depth of
+/// their samples. Both must be of the same color type.
+fn paste(output: &mut DynamicImage, block: DynamicImage, x: u32, y: u32) {
+	let (x, y) = (x as i64, y as i64);
+	match (output, block) {
+		(DynamicImage::ImageLuma8(out), DynamicImage::ImageLuma8(img)) => {
+			replace(out, &img, x, y)
+		}
+		(
+			DynamicImage::ImageLumaA8(out),
+			DynamicImage::ImageLumaA8(img),
+		) => replace(out, &img, x, y),
+		(DynamicImage::ImageRgb8(out), DynamicImage::ImageRgb8(img)) => {
+			replace(out, &img, x, y)
+		}
+		(DynamicImage::ImageRgba8(out), DynamicImage::ImageRgba8(img)) => {
+			replace(out, &img, x, y)
+		}
+		(
+			DynamicImage::ImageLuma16(out),
+			DynamicImage::ImageLuma16(img),
+		) => replace(out, &img, x, y),
+		(
+			DynamicImage::ImageLumaA16(out),
+			DynamicImage::ImageLumaA16(img),
+		) => replace(out, &img, x, y),
+		(DynamicImage::ImageRgb16(out), DynamicImage::ImageRgb16(img)) => {
+			replace(out, &img, x, y)
+		}
+		(
+			DynamicImage::ImageRgba16(out),
+			DynamicImage::ImageRgba16(img),
+		) => replace(out, &img, x, y),
+		(
+			DynamicImage::ImageRgb32F(out),
+			DynamicImage::ImageRgb32F(img),
+		) => replace(out, &img, x, y),
+		(
+			DynamicImage::ImageRgba32F(out),
+			DynamicImage::ImageRgba32F(img),
+		) => replace(out, &img, x, y),
+		_ => unreachable!("the block must be of the output's color type"),
+	}
+}
+
 impl From<Pixlzr> for DynamicImage {
 	fn from(value: Pixlzr) -> Self {
 		value.to_image(value.filter.unwrap_or(FilterType::Gaussian))
 	}
 }
+
+pub mod tests_on_pixlzr_image {
+	#[allow(unused_imports)]
+	use super::{FilterType, Pixlzr};
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use image::{DynamicImage, GenericImageView, RgbImage};
+
+	#[test]
+	fn test_to_image_scaled() {
+		// Four flat quadrants, over a grid with trailing blocks
+		let img = RgbImage::from_fn(40, 24, |x, y| {
+			image::Rgb([if x < 20 { 200 } else { 10 }, (y / 12) as u8, 0])
+		});
+		let mut pix =
+			Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 10, 12);
+		pix.shrink_by(FilterType::Nearest, 1., &PowerOfTwo);
+
+		// At full size, it matches the expansion
+		assert_eq!(
+			pix.to_image_scaled(40, 24, FilterType::Nearest).as_bytes(),
+			pix.to_image(FilterType::Nearest).as_bytes()
+		);
+
+		let thumb = pix.to_image_scaled(10, 6, FilterType::Nearest);
+		assert_eq!(thumb.dimensions(), (10, 6));
+		assert_eq!(thumb.get_pixel(0, 0).0, [200, 0, 0, 255]);
+		assert_eq!(thumb.get_pixel(9, 5).0, [10, 1, 0, 255]);
+	}
+}
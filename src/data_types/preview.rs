@@ -0,0 +1,71 @@
+use super::pixlzr::Pixlzr;
+use crate::operations::get_block_average;
+
+#[cfg(feature = "image-rs")]
+use image::RgbaImage;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+/// A low resolution render of a PIXLZR image, with one RGBA8 pixel per
+/// block, holding the block's average color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Preview {
+	pub width: u32,
+	pub height: u32,
+	/// The RGBA8 pixels, line by line
+	pub data: Vec<u8>,
+}
+
+impl Preview {
+	/// Returns the width and height of the preview as a tuple of u32.
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+
+	#[cfg(feature = "image-rs")]
+	pub fn to_image(&self) -> RgbaImage {
+		RgbaImage::from_raw(self.width, self.height, self.data.clone())
+			.expect("the preview has one RGBA8 pixel per block")
+	}
+}
+
+impl Pixlzr {
+	/// Renders the preview of the image, with one pixel per cell of the
+	/// block grid.
+	///
+	/// For the tree layout, each cell's color is the average of its
+	/// blocks, weighted by their area.
+	pub fn render_preview(&self) -> Preview {
+		let (width, height) = self.block_grid_dimensions();
+		let averages: Vec<_> =
+			self.blocks.par_iter().map(get_block_average).collect();
+
+		let mut sums = vec![[0f32; 4]; (width * height) as usize];
+		let mut areas = vec![0f32; sums.len()];
+		for (average, rect) in averages.into_iter().zip(self.block_rects())
+		{
+			let cell = (rect.y / self.block_height * width
+				+ rect.x / self.block_width) as usize;
+			let area = (rect.width * rect.height) as f32;
+			for (sum, sample) in sums[cell].iter_mut().zip(average) {
+				*sum += sample * area;
+			}
+			areas[cell] += area;
+		}
+
+		let data = sums
+			.into_iter()
+			.zip(areas)
+			.flat_map(|(sum, area)| {
+				sum.map(|sample| {
+					(sample / area.max(1.)).clamp(0., 1.) * 255.
+				})
+				.map(|sample| sample.round() as u8)
+			})
+			.collect();
+		Preview {
+			width,
+			height,
+			data,
+		}
+	}
+}
@@ -1,4 +1,7 @@
-use core::cmp::Ordering;
+use core::{
+	cmp::Ordering,
+	fmt::{Display, Formatter, Result as FMTResult},
+};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
 pub struct Semver {
@@ -33,6 +36,12 @@ impl Ord for Semver {
 	}
 }
 
+impl Display for Semver {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FMTResult {
+		write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+	}
+}
+
 impl PartialOrd for Semver {
 	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
 		Some(self.cmp(other))
@@ -0,0 +1,191 @@
+use super::{pixlzr::Pixlzr, Compression, FilterType, Metadata};
+use crate::{
+	analyzer::BlockAnalyzer,
+	error::{PixlzrError, Result},
+	policy::ShrinkPolicy,
+};
+
+#[cfg(feature = "image-rs")]
+use image::DynamicImage;
+
+/// The default `threshold` of a `PixlzrSequence`: only unchanged blocks are
+/// stored as references
+pub const DEFAULT_SEQUENCE_THRESHOLD: f32 = 0.;
+
+/// A frame of a `PixlzrSequence`
+#[derive(Clone)]
+pub struct PixlzrFrame {
+	pub image: Pixlzr,
+	/// How long the frame is shown, in milliseconds, as a ratio of
+	/// (numerator, denominator)
+	pub delay: (u32, u32),
+}
+
+/// A sequence of PIXLZR images, all over the same block grid, stored
+/// together.
+///
+/// When encoded, each block that is unchanged from the previous frame, or
+/// differs from it by at most `threshold`, is stored as a reference to it.
+#[derive(Clone)]
+pub struct PixlzrSequence {
+	pub width: u32,
+	pub height: u32,
+	pub block_width: u32,
+	pub block_height: u32,
+	/// The biggest mean difference, in `[0; 1]`, of a block to the one at
+	/// its place in the previous frame, for it to be stored as a reference
+	pub threshold: f32,
+	/// The second stage compression, used when encoding each frame
+	pub compression: Compression,
+	/// The metadata of the whole sequence
+	pub metadata: Metadata,
+	pub frames: Vec<PixlzrFrame>,
+}
+
+impl PixlzrSequence {
+	/// Creates an empty sequence, of frames of `width` x `height` pixels,
+	/// split in blocks of `block_width` x `block_height` pixels.
+	pub fn new(
+		width: u32,
+		height: u32,
+		block_width: u32,
+		block_height: u32,
+	) -> Self {
+		Self {
+			width,
+			height,
+			block_width,
+			block_height,
+			threshold: DEFAULT_SEQUENCE_THRESHOLD,
+			compression: Compression::None,
+			metadata: Metadata::default(),
+			frames: vec![],
+		}
+	}
+
+	/// Returns the width and height of the frames as a tuple of u32.
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+	/// Returns the common block dimensions in the frames as a tuple of
+	/// (width, height).
+	pub fn block_dimensions(&self) -> (u32, u32) {
+		(self.block_width, self.block_height)
+	}
+	/// The amount of frames.
+	pub fn len(&self) -> usize {
+		self.frames.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty()
+	}
+	/// Returns an iterator over the frames, in order.
+	pub fn iter(&self) -> std::slice::Iter<'_, PixlzrFrame> {
+		self.frames.iter()
+	}
+
+	/// Appends a frame, shown for `delay` milliseconds, as a ratio of
+	/// (numerator, denominator).
+	///
+	/// Fails with `PixlzrError::FrameMismatch` if the `image` doesn't have
+	/// the sequence's dimensions and block dimensions.
+	pub fn push_frame(
+		&mut self,
+		image: Pixlzr,
+		delay: (u32, u32),
+	) -> Result<()> {
+		if !self.fits(&image) {
+			return Err(PixlzrError::FrameMismatch {
+				index: self.frames.len(),
+			});
+		}
+		self.frames.push(PixlzrFrame { image, delay });
+		Ok(())
+	}
+
+	#[cfg(feature = "image-rs")]
+	/// Splits the `image` into the sequence's blocks, and appends it as a
+	/// frame.
+	///
+	/// See `push_frame`.
+	pub fn push_image(
+		&mut self,
+		image: &DynamicImage,
+		delay: (u32, u32),
+	) -> Result<()> {
+		let image =
+			Pixlzr::from_image(image, self.block_width, self.block_height);
+		self.push_frame(image, delay)
+	}
+
+	/// Shrinks every frame, as with `Pixlzr::shrink_by`.
+	pub fn shrink_by<P>(
+		&mut self,
+		filter_downscale: FilterType,
+		factor: f32,
+		policy: &P,
+	) where
+		P: ShrinkPolicy + ?Sized,
+	{
+		for frame in self.frames.iter_mut() {
+			frame.image.shrink_by(filter_downscale, factor, policy);
+		}
+	}
+	/// Shrinks every frame, as with `Pixlzr::shrink_directionally`.
+	pub fn shrink_directionally<P>(
+		&mut self,
+		filter_downscale: FilterType,
+		factor: f32,
+		policy: &P,
+	) where
+		P: ShrinkPolicy + ?Sized,
+	{
+		for frame in self.frames.iter_mut() {
+			frame.image.shrink_directionally(
+				filter_downscale,
+				factor,
+				policy,
+			);
+		}
+	}
+
+	/// Shrinks every frame, as with `Pixlzr::shrink_with`.
+	pub fn shrink_with<A, P>(
+		&mut self,
+		filter_downscale: FilterType,
+		analyzer: &A,
+		factor: f32,
+		policy: &P,
+	) where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+	{
+		for frame in self.frames.iter_mut() {
+			frame.image.shrink_with(
+				filter_downscale,
+				analyzer,
+				factor,
+				policy,
+			);
+		}
+	}
+
+	/// Checks if `image` has the sequence's dimensions and block grid
+	pub(crate) fn fits(&self, image: &Pixlzr) -> bool {
+		image.is_grid()
+			&& image.dimensions() == self.dimensions()
+			&& image.block_dimensions() == self.block_dimensions()
+			&& image.blocks.len()
+				== (image.block_grid_width() * image.block_grid_height())
+					as usize
+	}
+}
+
+impl<'a> IntoIterator for &'a PixlzrSequence {
+	type Item = &'a PixlzrFrame;
+	type IntoIter = std::slice::Iter<'a, PixlzrFrame>;
+
+	fn into_iter(self) -> Self::IntoIter {
+		self.frames.iter()
+	}
+}
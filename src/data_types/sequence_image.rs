@@ -0,0 +1,285 @@
+use super::{sequence::PixlzrSequence, FilterType};
+use crate::error::{PixlzrError, Result};
+
+use image::{
+	codecs::gif::{GifEncoder, Repeat},
+	error::{EncodingError, ImageFormatHint},
+	imageops::replace,
+	Delay, DynamicImage, Frame, ImageError, ImageFormat, ImageResult,
+};
+use std::io::Write;
+
+impl PixlzrSequence {
+	/// Splits each frame of an animation, such as the one of
+	/// `AnimationDecoder::into_frames`, into blocks of `block_width` x
+	/// `block_height` pixels, keeping their delays.
+	///
+	/// The sequence takes the dimensions of the first frame. Frames placed
+	/// at an offset, or smaller than it, are drawn over the previous one.
+	pub fn from_animation<I>(
+		frames: I,
+		block_width: u32,
+		block_height: u32,
+	) -> Result<Self>
+	where
+		I: IntoIterator<Item = ImageResult<Frame>>,
+	{
+		let mut frames = frames.into_iter();
+		let mut canvas = match frames.next() {
+			Some(frame) => frame?,
+			None => return Ok(Self::new(0, 0, block_width, block_height)),
+		};
+		let mut sequence = Self::new(
+			canvas.buffer().width(),
+			canvas.buffer().height(),
+			block_width,
+			block_height,
+		);
+		loop {
+			let delay = canvas.delay().numer_denom_ms();
+			let image = DynamicImage::ImageRgba8(canvas.into_buffer());
+			sequence.push_image(&image, delay)?;
+
+			let Some(frame) = frames.next() else {
+				break;
+			};
+			let frame = frame?;
+			canvas = if frame.left() == 0
+				&& frame.top() == 0
+				&& frame.buffer().dimensions() == sequence.dimensions()
+			{
+				frame
+			} else {
+				let mut buffer = image.into_rgba8();
+				replace(
+					&mut buffer,
+					frame.buffer(),
+					frame.left() as i64,
+					frame.top() as i64,
+				);
+				Frame::from_parts(buffer, 0, 0, frame.delay())
+			};
+		}
+		Ok(sequence)
+	}
+
+	/// Expands each frame into an image, with its delay.
+	pub fn to_frames(&self, filter: FilterType) -> Vec<Frame> {
+		self.frames
+			.iter()
+			.map(|frame| {
+				let (numerator, denominator) = frame.delay;
+				Frame::from_parts(
+					frame.image.to_image(filter).into_rgba8(),
+					0,
+					0,
+					Delay::from_numer_denom_ms(numerator, denominator),
+				)
+			})
+			.collect()
+	}
+
+	/// Expands the sequence into an endlessly looping animated GIF.
+	///
+	/// GIF delays are kept in hundredths of a second.
+	pub fn write_gif<W: Write>(
+		&self,
+		writer: W,
+		filter: FilterType,
+	) -> Result<()> {
+		let mut encoder = GifEncoder::new(writer);
+		encoder.set_repeat(Repeat::Infinite)?;
+		encoder.encode_frames(self.to_frames(filter))?;
+		Ok(())
+	}
+
+	/// Expands the sequence into an endlessly looping animated PNG.
+	pub fn write_apng<W: Write>(
+		&self,
+		writer: W,
+		filter: FilterType,
+	) -> Result<()> {
+		let frames = self.to_frames(filter);
+		let mut encoder =
+			png::Encoder::new(writer, self.width, self.height);
+		encoder.set_color(png::ColorType::Rgba);
+		encoder.set_depth(png::BitDepth::Eight);
+		encoder
+			.set_animated(frames.len() as u32, 0)
+			.map_err(png_error)?;
+		let mut writer = encoder.write_header().map_err(png_error)?;
+		for frame in frames {
+			let (numerator, denominator) = apng_delay(frame.delay());
+			writer
+				.set_frame_delay(numerator, denominator)
+				.map_err(png_error)?;
+			writer
+				.write_image_data(frame.buffer().as_raw())
+				.map_err(png_error)?;
+		}
+		writer.finish().map_err(png_error)?;
+		Ok(())
+	}
+}
+
+/// The delay of an APNG frame, in seconds, as a ratio of 16 bits integers
+fn apng_delay(delay: Delay) -> (u16, u16) {
+	let (numerator, denominator) = delay.numer_denom_ms();
+	let (mut numerator, mut denominator) =
+		(numerator as u64, denominator as u64 * 1000);
+	let divisor = gcd(numerator, denominator);
+	(numerator, denominator) =
+		(numerator / divisor, denominator / divisor);
+	if numerator <= u16::MAX as u64 && denominator <= u16::MAX as u64 {
+		return (numerator as u16, denominator as u16);
+	}
+	// Rounds to the millisecond, or to the second for the longest delays
+	let ms = (numerator as f64 * 1000. / denominator as f64).round();
+	if ms <= u16::MAX as f64 {
+		(ms as u16, 1000)
+	} else {
+		((ms / 1000.).round().min(u16::MAX as f64) as u16, 1)
+	}
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+	a.max(1)
+}
+
+fn png_error(err: png::EncodingError) -> PixlzrError {
+	ImageError::Encoding(EncodingError::new(
+		ImageFormatHint::Exact(ImageFormat::Png),
+		err,
+	))
+	.into()
+}
+
+pub mod tests_on_sequence_image {
+	#[allow(unused_imports)]
+	use super::{apng_delay, PixlzrSequence};
+	#[allow(unused_imports)]
+	use crate::data_types::FilterType;
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use image::{
+		codecs::{gif::GifDecoder, png::PngDecoder},
+		AnimationDecoder, Delay, Frame, RgbaImage,
+	};
+	#[allow(unused_imports)]
+	use std::io::Cursor;
+
+	#[allow(dead_code)]
+	fn sample_frames() -> Vec<Frame> {
+		(0..3u32)
+			.map(|i| {
+				let buffer = RgbaImage::from_fn(24, 16, |x, y| {
+					image::Rgba([
+						((x + i * 8) * 10) as u8,
+						(y * 15) as u8,
+						(i * 80) as u8,
+						255,
+					])
+				});
+				let delay = Delay::from_numer_denom_ms(100 + i * 50, 1);
+				Frame::from_parts(buffer, 0, 0, delay)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_from_animation() {
+		let mut frames = sample_frames();
+		// A partial frame, drawn over the previous one
+		frames.push(Frame::from_parts(
+			RgbaImage::from_pixel(4, 4, image::Rgba([1, 2, 3, 255])),
+			8,
+			8,
+			Delay::from_numer_denom_ms(40, 1),
+		));
+		let sequence = PixlzrSequence::from_animation(
+			frames.into_iter().map(Ok),
+			8,
+			8,
+		)
+		.unwrap();
+		assert_eq!(sequence.len(), 4);
+		assert_eq!(sequence.dimensions(), (24, 16));
+		let delays: Vec<_> = sequence.iter().map(|f| f.delay).collect();
+		assert_eq!(delays, [(100, 1), (150, 1), (200, 1), (40, 1)]);
+
+		let frames = sequence.to_frames(FilterType::Nearest);
+		assert_eq!(frames[3].buffer().get_pixel(9, 9).0, [1, 2, 3, 255]);
+		assert_eq!(
+			frames[3].buffer().get_pixel(0, 0),
+			frames[2].buffer().get_pixel(0, 0)
+		);
+	}
+
+	#[test]
+	fn test_gif_round_trip() {
+		let mut sequence = PixlzrSequence::from_animation(
+			sample_frames().into_iter().map(Ok),
+			8,
+			8,
+		)
+		.unwrap();
+		sequence.shrink_by(FilterType::Lanczos3, 1., &PowerOfTwo);
+
+		let mut data = vec![];
+		sequence.write_gif(&mut data, FilterType::Nearest).unwrap();
+		let decoder = GifDecoder::new(Cursor::new(data)).unwrap();
+		let frames = decoder.into_frames().collect_frames().unwrap();
+		assert_eq!(frames.len(), 3);
+		let delays: Vec<_> =
+			frames.iter().map(|f| f.delay().numer_denom_ms()).collect();
+		assert_eq!(delays, [(100, 1), (150, 1), (200, 1)]);
+	}
+
+	#[test]
+	fn test_apng_round_trip() {
+		let sequence = PixlzrSequence::from_animation(
+			sample_frames().into_iter().map(Ok),
+			8,
+			8,
+		)
+		.unwrap();
+
+		let mut data = vec![];
+		sequence.write_apng(&mut data, FilterType::Nearest).unwrap();
+		let decoder = PngDecoder::new(Cursor::new(data)).unwrap();
+		assert!(decoder.is_apng().unwrap());
+		let frames = decoder
+			.apng()
+			.unwrap()
+			.into_frames()
+			.collect_frames()
+			.unwrap();
+		assert_eq!(frames.len(), 3);
+		let delays: Vec<_> =
+			frames.iter().map(|f| f.delay().numer_denom_ms()).collect();
+		assert_eq!(delays, [(100, 1), (150, 1), (200, 1)]);
+		// Unshrunk frames are lossless
+		let expected = sequence.to_frames(FilterType::Nearest);
+		assert_eq!(frames[1].buffer(), expected[1].buffer());
+	}
+
+	#[test]
+	fn test_apng_delay() {
+		assert_eq!(
+			apng_delay(Delay::from_numer_denom_ms(100, 1)),
+			(1, 10)
+		);
+		assert_eq!(
+			apng_delay(Delay::from_numer_denom_ms(1000, 3)),
+			(1, 3)
+		);
+		assert_eq!(
+			apng_delay(Delay::from_numer_denom_ms(70_001, 1)),
+			(70, 1)
+		);
+	}
+}
@@ -0,0 +1,308 @@
+use super::{pixlzr::Pixlzr, FilterType};
+use crate::{
+	analyzer::{BlockAnalyzer, OklabDeviation, Sobel},
+	error::Result,
+	metrics::{self, Quality},
+	operations::reduce_image_section,
+	policy::ShrinkPolicy,
+};
+
+use rayon::iter::{
+	IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+
+/// The exponents of the smallest and largest factors searched, as powers
+/// of two
+const FACTOR_EXPONENTS: (f32, f32) = (-16., 16.);
+/// The number of halvings of the searched exponents
+const SEARCH_STEPS: usize = 14;
+
+/// What `Pixlzr::shrink_to_target` aims for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Target {
+	/// An encoded size of at most this many octets
+	Bytes(usize),
+	/// A PSNR, in dB, of at least this value
+	Psnr(f32),
+	/// An SSIM of at least this value
+	Ssim(f32),
+}
+
+/// The outcome of `Pixlzr::shrink_to_target`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetResult {
+	/// The chosen factor, as given to `shrink_by` or `shrink_directionally`
+	pub factor: f32,
+	/// The encoded size, in octets
+	pub size: usize,
+	/// The distortion against the image before shrinking
+	pub quality: Quality,
+}
+
+impl Pixlzr {
+	/// Shrinks the image as `shrink_by` does, with the factor that best
+	/// reaches the `target`: the largest one within a size, or the
+	/// smallest one reaching a quality.
+	///
+	/// The encoded size takes the current `compression` and `preview`,
+	/// and the quality is measured against the image before shrinking.
+	/// If the target can't be reached, shrinks by the closest factor.
+	pub fn shrink_to_target<P>(
+		&mut self,
+		filter_downscale: FilterType,
+		target: Target,
+		policy: &P,
+	) -> Result<TargetResult>
+	where
+		P: ShrinkPolicy + ?Sized,
+	{
+		self.shrink_to_target_with(
+			filter_downscale,
+			&OklabDeviation::default(),
+			target,
+			policy,
+		)
+	}
+
+	/// Shrinks the image as `shrink_directionally` does, every block even
+	/// if shrunk already, with the factor that best reaches the `target`,
+	/// as `shrink_to_target` does.
+	pub fn shrink_directionally_to_target<P>(
+		&mut self,
+		filter_downscale: FilterType,
+		target: Target,
+		policy: &P,
+	) -> Result<TargetResult>
+	where
+		P: ShrinkPolicy + ?Sized,
+	{
+		let values = self.block_values(&Sobel, true);
+		self.search_target(filter_downscale, target, &values, policy)
+	}
+
+	/// Shrinks the image as `shrink_with` does, with the factor that best
+	/// reaches the `target`, as `shrink_to_target` does.
+	///
+	/// The `analyzer` measures each block only once.
+	pub fn shrink_to_target_with<A, P>(
+		&mut self,
+		filter_downscale: FilterType,
+		analyzer: &A,
+		target: Target,
+		policy: &P,
+	) -> Result<TargetResult>
+	where
+		A: BlockAnalyzer + ?Sized,
+		P: ShrinkPolicy + ?Sized,
+	{
+		let values = self.block_values(analyzer, false);
+		self.search_target(filter_downscale, target, &values, policy)
+	}
+
+	/// The values of each block, for a factor of 1: of every one if
+	/// `every`, or else of the ones not shrunk yet
+	fn block_values<A>(
+		&self,
+		analyzer: &A,
+		every: bool,
+	) -> Vec<Option<(f32, f32)>>
+	where
+		A: BlockAnalyzer + ?Sized,
+	{
+		self.blocks
+			.par_iter()
+			.map(|block| {
+				(every || block.block_value().is_none())
+					.then(|| analyzer.analyze(block))
+			})
+			.collect()
+	}
+
+	/// A copy of the image, its blocks shrunk by `factor` times their
+	/// `values`, to the scales the `policy` gives them
+	fn shrunk_by_values<P>(
+		&self,
+		filter_downscale: FilterType,
+		values: &[Option<(f32, f32)>],
+		factor: f32,
+		policy: &P,
+	) -> Pixlzr
+	where
+		P: ShrinkPolicy + ?Sized,
+	{
+		let blocks = self
+			.blocks
+			.par_iter()
+			.zip(values)
+			.map(|(block, value)| match value {
+				Some((hz, vr)) => reduce_image_section(
+					(hz * factor, vr * factor),
+					block,
+					filter_downscale,
+					policy,
+				),
+				None => block.clone(),
+			})
+			.collect();
+
+		Self {
+			width: self.width,
+			height: self.height,
+			block_width: self.block_width,
+			block_height: self.block_height,
+			filter: self.filter,
+			compression: self.compression,
+			metadata: self.metadata.clone(),
+			preview: self.preview,
+			layout: self.layout.clone(),
+			blocks,
+		}
+	}
+
+	/// Searches the factor of `values` that best reaches the `target`, by
+	/// bisection of its exponent, and shrinks the image by it
+	fn search_target<P>(
+		&mut self,
+		filter_downscale: FilterType,
+		target: Target,
+		values: &[Option<(f32, f32)>],
+		policy: &P,
+	) -> Result<TargetResult>
+	where
+		P: ShrinkPolicy + ?Sized,
+	{
+		let source = self.to_image(filter_downscale);
+		let meets = |exponent: f32| -> Result<bool> {
+			let pix = self.shrunk_by_values(
+				filter_downscale,
+				values,
+				exponent.exp2(),
+				policy,
+			);
+			let image = || pix.to_image(filter_downscale);
+			Ok(match target {
+				Target::Bytes(size) => pix.encode_to_vec()?.len() <= size,
+				Target::Psnr(psnr) => {
+					metrics::psnr(&source, &image())? >= psnr
+				}
+				Target::Ssim(ssim) => {
+					metrics::ssim(&source, &image())? >= ssim
+				}
+			})
+		};
+
+		// Larger factors shrink less: sizes grow, and qualities with them
+		let bytes = matches!(target, Target::Bytes(_));
+		let (mut low, mut high) = FACTOR_EXPONENTS;
+		for _ in 0..SEARCH_STEPS {
+			let middle = (low + high) / 2.;
+			if meets(middle)? == bytes {
+				low = middle;
+			} else {
+				high = middle;
+			}
+		}
+		let factor = if bytes { low } else { high }.exp2();
+
+		*self = self.shrunk_by_values(
+			filter_downscale,
+			values,
+			factor,
+			policy,
+		);
+		Ok(TargetResult {
+			factor,
+			size: self.encode_to_vec()?.len(),
+			quality: metrics::compare(
+				&source,
+				&self.to_image(filter_downscale),
+			)?,
+		})
+	}
+}
+
+pub mod tests_on_target {
+	#[allow(unused_imports)]
+	use super::{Pixlzr, Target};
+	#[allow(unused_imports)]
+	use crate::data_types::test_images::noise;
+	#[allow(unused_imports)]
+	use crate::data_types::FilterType;
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use image::{DynamicImage, RgbImage};
+
+	#[allow(dead_code)]
+	fn sample_pixlzr() -> Pixlzr {
+		let img = RgbImage::from_fn(64, 48, |x, y| {
+			let noise = noise(x, y, 61);
+			image::Rgb([(x * 3 + noise) as u8, (y * 5) as u8, 90])
+		});
+		Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16)
+	}
+
+	#[test]
+	fn test_target_bytes() {
+		let full = sample_pixlzr().encode_to_vec().unwrap().len();
+		let mut pix = sample_pixlzr();
+		let result = pix
+			.shrink_to_target(
+				FilterType::Nearest,
+				Target::Bytes(full / 2),
+				&PowerOfTwo,
+			)
+			.unwrap();
+		assert!(result.size <= full / 2);
+		assert_eq!(result.size, pix.encode_to_vec().unwrap().len());
+
+		// The same factor through `shrink_by` gives the same image
+		let mut by = sample_pixlzr();
+		by.shrink_by(FilterType::Nearest, result.factor, &PowerOfTwo);
+		assert_eq!(by.encode_to_vec().unwrap().len(), result.size);
+	}
+
+	#[test]
+	fn test_target_directionally_shrunk() {
+		// Shrinks the shrunk blocks again, as `shrink_directionally`
+		let mut shrunk = sample_pixlzr();
+		shrunk.shrink_directionally(FilterType::Nearest, 4., &PowerOfTwo);
+		let mut pix = shrunk.clone();
+		let result = pix
+			.shrink_directionally_to_target(
+				FilterType::Nearest,
+				Target::Bytes(0),
+				&PowerOfTwo,
+			)
+			.unwrap();
+		let mut by = shrunk;
+		by.shrink_directionally(
+			FilterType::Nearest,
+			result.factor,
+			&PowerOfTwo,
+		);
+		assert_eq!(by.block_scale_levels(), pix.block_scale_levels());
+	}
+
+	#[test]
+	fn test_target_quality() {
+		let mut psnr = sample_pixlzr();
+		let psnr = psnr
+			.shrink_to_target(
+				FilterType::Triangle,
+				Target::Psnr(20.),
+				&PowerOfTwo,
+			)
+			.unwrap();
+		let mut ssim = sample_pixlzr();
+		let ssim = ssim
+			.shrink_directionally_to_target(
+				FilterType::Triangle,
+				Target::Ssim(0.9),
+				&PowerOfTwo,
+			)
+			.unwrap();
+		assert!(psnr.quality.psnr >= 20.);
+		assert!(ssim.quality.ssim >= 0.9);
+	}
+}
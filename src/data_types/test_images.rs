@@ -0,0 +1,8 @@
+//! Helpers for the tests on noisy images.
+
+/// A deterministic noise for the pixel at (`x`, `y`), below `modulus`,
+/// to give the blocks of a test image some detail
+#[allow(dead_code)]
+pub(crate) fn noise(x: u32, y: u32, modulus: u32) -> u32 {
+	(x * 7919 + y * 104729) % modulus
+}
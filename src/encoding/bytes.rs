@@ -1,81 +1,158 @@
-#![allow(clippy::uninit_vec)]
-use super::Raw;
-
-macro_rules! safe_copy {
-	($vec:ident[$index:expr] +=[$len:expr] $slice:ident) => {{
-		let new_index = $index + $len;
-		// Method 1: resize the vector and initialize with 0s
-		// $vec.resize(new_index, 0);
-		// Method 2: reserve space (the capacity), and sets the length
-		// - It's safe because it guarantees the capacity, and initializes the data
-		$vec.reserve($len);
-		unsafe { $vec.set_len(new_index) };
-		// Copies the data from the slice
-		$vec[$index..new_index].copy_from_slice($slice);
-		// Returns the new index
-		new_index
-	}};
-	($vec:ident[$index:expr] += $slice:ident) => {{
-		let len = $slice.len();
-		let new_index = $index + len;
-		// Method 1: resize the vector and initialize with 0s
-		// $vec.resize(new_index, 0);
-		// Method 2: reserve space (the capacity), and sets the length
-		// - It's safe because it guarantees the capacity, and initializes the data
-		$vec.reserve(len);
-		unsafe { $vec.set_len(new_index) };
-		// Copies the data from the slice
-		$vec[$index..new_index].copy_from_slice($slice);
-		// Returns the new index
-		new_index
-	}};
+use crate::error::{PixlzrError, Result};
+use core::ops::Range;
+use std::io::{ErrorKind, Read};
+
+/// A source of big-endian numbers and octets, with bounds checking.
+///
+/// It's implemented by `Reader`, over a borrowed buffer, and by
+/// `StreamReader`, over any `std::io::Read`.
+/// Every `read_*` method fails with `PixlzrError::Truncated` when there are
+/// not enough octets left.
+pub trait ByteReader {
+	/// The position of the next octet to be read, relative to the start of
+	/// the original buffer or stream.
+	fn position(&self) -> usize;
+
+	/// Fills `buf` with the next `buf.len()` octets.
+	fn read_into(&mut self, buf: &mut [u8]) -> Result<()>;
+
+	/// Reads the next `len` octets into a new vector.
+	fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+		let mut buf = vec![0; len];
+		self.read_into(&mut buf)?;
+		Ok(buf)
+	}
+
+	fn read_array<const N: usize>(&mut self) -> Result<[u8; N]> {
+		let mut array = [0; N];
+		self.read_into(&mut array)?;
+		Ok(array)
+	}
+
+	fn read_u8(&mut self) -> Result<u8> {
+		Ok(u8::from_be_bytes(self.read_array()?))
+	}
+
+	fn read_u16(&mut self) -> Result<u16> {
+		Ok(u16::from_be_bytes(self.read_array()?))
+	}
+
+	fn read_u32(&mut self) -> Result<u32> {
+		Ok(u32::from_be_bytes(self.read_array()?))
+	}
+
+	fn read_f32(&mut self) -> Result<f32> {
+		Ok(f32::from_be_bytes(self.read_array()?))
+	}
+
+	fn read_u64(&mut self) -> Result<u64> {
+		Ok(u64::from_be_bytes(self.read_array()?))
+	}
+
+	fn read_f64(&mut self) -> Result<f64> {
+		Ok(f64::from_be_bytes(self.read_array()?))
+	}
+
+	/// Reads an unsigned LEB128 variable-length integer: 7 bits per octet,
+	/// least significant first, with the highest bit set on all but the
+	/// last octet.
+	fn read_varint(&mut self) -> Result<u64> {
+		let offset = self.position();
+		let mut number = 0u64;
+		for shift in (0..64).step_by(7) {
+			let octet = self.read_u8()?;
+			let bits = (octet & 0x7f) as u64;
+			if shift == 63 && bits > 1 {
+				break;
+			}
+			number |= bits << shift;
+			if octet & 0x80 == 0 {
+				return Ok(number);
+			}
+		}
+		Err(PixlzrError::Invalid {
+			offset,
+			reason: "the variable-length integer overflows 64 bits",
+		})
+	}
+
+	/// Reads a variable-length integer, that must fit in an `usize`.
+	fn read_varint_usize(&mut self) -> Result<usize> {
+		let offset = self.position();
+		usize::try_from(self.read_varint()?).map_err(|_| {
+			PixlzrError::Invalid {
+				offset,
+				reason: "the variable-length integer overflows usize",
+			}
+		})
+	}
 }
 
-/// The `Bytes` struct represents a byte buffer with additional functionality.
-/// It allows for creating new `Bytes` objects with initial data and index values,
-/// reserving capacity for new `Bytes` objects, and checking if there are more
-/// elements in the byte buffer to be read.
+/// The `Reader` struct is a cursor over a borrowed byte buffer.
+/// It allows for reading big-endian numbers and slices from the buffer,
+/// checking if there are more elements to be read, and creating
+/// sub-views of the buffer without copying it.
+///
+/// The numbers are read through the `ByteReader` trait.
 ///
 /// # Examples
 ///
 /// ```
-/// use pixlzr::encoding::bytes::Bytes;
-///
-/// let bytes = Bytes::new(vec![1, 2, 3]);
-/// assert_eq!(bytes.yet_readding(), true);
+/// use pixlzr::encoding::bytes::{ByteReader, Reader};
 ///
-/// let reserved_bytes = Bytes::reserve(10);
-/// assert_eq!(reserved_bytes.yet_readding(), false);
+/// let data = [0, 0, 0, 42, 7];
+/// let mut reader = Reader::new(&data);
+/// assert_eq!(reader.read_u32().unwrap(), 42);
+/// assert_eq!(reader.yet_readding(), true);
+/// assert_eq!(reader.read_u8().unwrap(), 7);
+/// assert_eq!(reader.yet_readding(), false);
+/// assert!(reader.read_u8().is_err());
 /// ```
-#[derive(Debug, Default)]
-pub struct Bytes {
-	pub data: Raw,
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Reader<'a> {
+	data: &'a [u8],
 	index: usize,
+	/// Position of `data` in the original buffer, for error reporting
+	offset: usize,
 }
 
-#[allow(unused)]
-impl Bytes {
-	/// Creates a new `Bytes` object with the given `data`, that will be read from 0 onwards.
-	pub fn new(data: Raw) -> Self {
-		Bytes { data, index: 0 }
+impl<'a> Reader<'a> {
+	/// Creates a new `Reader` over the given `data`, that will be read from 0 onwards.
+	pub fn new(data: &'a [u8]) -> Self {
+		Reader {
+			data,
+			index: 0,
+			offset: 0,
+		}
+	}
+
+	/// Creates a new `Reader` over the given `data`, that starts at `offset`
+	/// of a bigger buffer or stream, for error reporting.
+	pub fn with_offset(data: &'a [u8], offset: usize) -> Self {
+		Reader {
+			data,
+			index: 0,
+			offset,
+		}
 	}
 
+	/// The index of the next octet to be read, relative to this view.
 	pub fn index(&self) -> usize {
 		self.index
 	}
 
-	/// Creates a new `Bytes` object with an empty `data` vector,
-	///  with a pre-reserved capacity to hold `length` elements.
-	pub fn reserve(length: usize) -> Self {
-		Self::new(Vec::with_capacity(length))
+	/// The total length of the buffer.
+	pub fn len(&self) -> usize {
+		self.data.len()
 	}
 
-	/// Creates a new `Bytes` object with an empty `data` vector,
-	///  with a pre-reserved capacity to hold *exactly* `length` elements.
-	pub fn reserve_exact(length: usize) -> Self {
-		let mut data = Vec::new();
-		data.reserve_exact(length);
-		Self::new(data)
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	/// The amount of octets yet to be read.
+	pub fn remaining(&self) -> usize {
+		self.data.len() - self.index
 	}
 
 	/// Checks if there are more elements in the byte buffer to be read.
@@ -84,184 +161,304 @@ impl Bytes {
 		self.data.len() > self.index
 	}
 
-	// /// Returns a view of the byte buffer from the current index to the next `length` octets.
-	// pub fn view_with_length(&self, length: usize) -> Self {
-	// 	Self::new(&self.data[self.index..self.index + length])
-	// }
+	/// Returns a new `Reader` over the `span` of this buffer, without copying it.
+	pub fn cutout(&self, span: Range<usize>) -> Result<Reader<'a>> {
+		let data =
+			self.data.get(span.clone()).ok_or(PixlzrError::Truncated {
+				offset: self.offset + span.start.min(self.data.len()),
+			})?;
+		Ok(Reader {
+			data,
+			index: 0,
+			offset: self.offset + span.start,
+		})
+	}
 }
 
-impl From<Raw> for Bytes {
-	fn from(value: Raw) -> Self {
+impl<'a> From<&'a [u8]> for Reader<'a> {
+	fn from(value: &'a [u8]) -> Self {
 		Self::new(value)
 	}
 }
 
-macro_rules! bytes_cutout {
-	($byte:ident[$span:expr]) => {
-		Bytes::from($byte.data[$span].to_owned())
-	};
+impl ByteReader for Reader<'_> {
+	fn position(&self) -> usize {
+		self.offset + self.index
+	}
+
+	fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+		buf.copy_from_slice(self.read_slice(buf.len())?);
+		Ok(())
+	}
+
+	/// Reads the next `len` octets into a new vector, checking the bounds
+	/// before allocating it.
+	fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+		Ok(self.read_slice(len)?.to_vec())
+	}
 }
 
-#[allow(unused)]
 /// Readding methods
-impl Bytes {
-	pub fn read_u8(&mut self) -> u8 {
-		let number = self.data[self.index];
-		self.index += 1;
-		number
-	}
-
-	pub fn read_u16(&mut self) -> u16 {
-		let number = u16::from_be_bytes([
-			self.data[self.index],
-			self.data[self.index + 1],
-		]);
-		self.index += 2;
-		number
-	}
-
-	pub fn read_u32(&mut self) -> u32 {
-		let number = u32::from_be_bytes([
-			self.data[self.index],
-			self.data[self.index + 1],
-			self.data[self.index + 2],
-			self.data[self.index + 3],
-		]);
-		self.index += 4;
-		number
-	}
-
-	pub fn read_f32(&mut self) -> f32 {
-		let number = f32::from_be_bytes([
-			self.data[self.index],
-			self.data[self.index + 1],
-			self.data[self.index + 2],
-			self.data[self.index + 3],
-		]);
-		self.index += 4;
-		number
-	}
-
-	pub fn read_u64(&mut self) -> u64 {
-		let number = u64::from_be_bytes([
-			self.data[self.index],
-			self.data[self.index + 1],
-			self.data[self.index + 2],
-			self.data[self.index + 3],
-			self.data[self.index + 4],
-			self.data[self.index + 5],
-			self.data[self.index + 6],
-			self.data[self.index + 7],
-		]);
-		self.index += 4;
-		number
-	}
-
-	pub fn read_f64(&mut self) -> f64 {
-		let number = f64::from_be_bytes([
-			self.data[self.index],
-			self.data[self.index + 1],
-			self.data[self.index + 2],
-			self.data[self.index + 3],
-			self.data[self.index + 4],
-			self.data[self.index + 5],
-			self.data[self.index + 6],
-			self.data[self.index + 7],
-		]);
-		self.index += 4;
-		number
-	}
-
-	pub fn read_slice(&mut self, len: usize) -> Option<&[u8]> {
+impl<'a> Reader<'a> {
+	/// Reads the next `len` octets, borrowing them from the buffer.
+	pub fn read_slice(&mut self, len: usize) -> Result<&'a [u8]> {
 		// Check if there are at least `len` elements to be readden
-		if self.data.len() < self.index + len {
-			return None;
+		let end = self
+			.index
+			.checked_add(len)
+			.filter(|&end| end <= self.data.len())
+			.ok_or(PixlzrError::Truncated {
+				offset: self.position(),
+			})?;
+
+		let slice = &self.data[self.index..end];
+		self.index = end;
+		Ok(slice)
+	}
+}
+
+/// The `StreamReader` struct reads big-endian numbers and octets from any
+/// `std::io::Read`, keeping track of its position.
+///
+/// An early end of the stream is reported as `PixlzrError::Truncated`.
+///
+/// # Examples
+///
+/// ```
+/// use pixlzr::encoding::bytes::{ByteReader, StreamReader};
+///
+/// let data: &[u8] = &[0, 0, 0, 42, 7];
+/// let mut reader = StreamReader::new(data);
+/// assert_eq!(reader.read_u32().unwrap(), 42);
+/// assert_eq!(reader.position(), 4);
+/// assert_eq!(reader.read_u8().unwrap(), 7);
+/// assert!(reader.read_u8().is_err());
+/// ```
+#[derive(Debug)]
+pub struct StreamReader<R: Read> {
+	inner: R,
+	position: usize,
+}
+
+impl<R: Read> StreamReader<R> {
+	pub fn new(inner: R) -> Self {
+		Self { inner, position: 0 }
+	}
+
+	/// Consumes the `StreamReader`, returning the underlying reader.
+	pub fn into_inner(self) -> R {
+		self.inner
+	}
+}
+
+impl<R: Read> ByteReader for StreamReader<R> {
+	fn position(&self) -> usize {
+		self.position
+	}
+
+	fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+		self.inner.read_exact(buf).map_err(|err| match err.kind() {
+			ErrorKind::UnexpectedEof => PixlzrError::Truncated {
+				offset: self.position,
+			},
+			_ => err.into(),
+		})?;
+		self.position += buf.len();
+		Ok(())
+	}
+
+	/// Reads the next `len` octets into a new vector, growing it only as the
+	/// octets arrive, so that a corrupted `len` can't allocate too much.
+	fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+		let mut buf = Vec::new();
+		(&mut self.inner).take(len as u64).read_to_end(&mut buf)?;
+		if buf.len() < len {
+			return Err(PixlzrError::Truncated {
+				offset: self.position + buf.len(),
+			});
+		}
+		self.position += len;
+		Ok(buf)
+	}
+}
+
+/// The `Writer` struct is a growable byte buffer, to which big-endian numbers
+/// and slices are appended.
+///
+/// # Examples
+///
+/// ```
+/// use pixlzr::encoding::bytes::Writer;
+///
+/// let mut writer = Writer::with_capacity(5);
+/// writer.write_u32(42);
+/// writer.write_u8(7);
+/// assert_eq!(writer.len(), 5);
+/// assert_eq!(writer.into_inner(), vec![0, 0, 0, 42, 7]);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Writer {
+	data: Vec<u8>,
+}
+
+impl Writer {
+	/// Creates a new, empty `Writer`.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Creates a new, empty `Writer`, with a pre-reserved capacity to hold
+	/// `length` elements.
+	pub fn with_capacity(length: usize) -> Self {
+		Self {
+			data: Vec::with_capacity(length),
 		}
+	}
+
+	/// The amount of octets written so far.
+	pub fn len(&self) -> usize {
+		self.data.len()
+	}
 
-		// println!("<>[{}..{}]", self.index, len);
-		let slice = self.data[self.index..(self.index + len)].into();
-		self.index += len;
-		Some(slice)
+	pub fn is_empty(&self) -> bool {
+		self.data.is_empty()
+	}
+
+	pub fn as_slice(&self) -> &[u8] {
+		&self.data
+	}
+
+	/// Consumes the `Writer`, returning the written octets.
+	pub fn into_inner(self) -> Vec<u8> {
+		self.data
+	}
+}
+
+impl From<Writer> for Vec<u8> {
+	fn from(value: Writer) -> Self {
+		value.into_inner()
 	}
 }
 
-#[allow(unused)]
 /// Writting methods
-impl Bytes {
+impl Writer {
 	pub fn write_u8(&mut self, number: u8) {
-		// let index2 = self.index + 1;
-		// self.data[self.index..index2]
-		// 	.copy_from_slice(&number.to_be_bytes());
-		// self.index = index2;
-		let data = &mut self.data;
-		let bytes = &[number];
-		self.index = safe_copy!(data[self.index] += [1] bytes);
+		self.data.push(number);
 	}
 
 	pub fn write_u16(&mut self, number: u16) {
-		// let index2 = self.index + 2;
-		// self.data[self.index..index2]
-		// 	.copy_from_slice(&number.to_be_bytes());
-		// self.index = index2;
-		let data = &mut self.data;
-		let bytes = &number.to_be_bytes();
-		self.index = safe_copy!(data[self.index] += [2] bytes);
+		self.write_slice(&number.to_be_bytes());
 	}
 
 	pub fn write_u32(&mut self, number: u32) {
-		// let index2 = self.index + 4;
-		// self.data[self.index..index2]
-		// 	.copy_from_slice(&number.to_be_bytes());
-		// self.index = index2;
-		let data = &mut self.data;
-		let bytes = &number.to_be_bytes();
-		self.index = safe_copy!(data[self.index] += [4] bytes);
+		self.write_slice(&number.to_be_bytes());
 	}
 
 	pub fn write_f32(&mut self, number: f32) {
-		// let index2 = self.index + 4;
-		// self.data[self.index..index2]
-		// 	.copy_from_slice(&number.to_be_bytes());
-		// self.index = index2;
-		let data = &mut self.data;
-		let bytes = &number.to_be_bytes();
-		self.index = safe_copy!(data[self.index] += [4] bytes);
+		self.write_slice(&number.to_be_bytes());
 	}
 
 	pub fn write_u64(&mut self, number: u64) {
-		// let index2 = self.index + 8;
-		// self.data[self.index..index2]
-		// 	.copy_from_slice(&number.to_be_bytes());
-		// self.index = index2;
-		let data = &mut self.data;
-		let bytes = &number.to_be_bytes();
-		self.index = safe_copy!(data[self.index] += [8] bytes);
+		self.write_slice(&number.to_be_bytes());
 	}
 
 	pub fn write_f64(&mut self, number: f64) {
-		// let index2 = self.index + 8;
-		// self.data[self.index..index2]
-		// 	.copy_from_slice(&number.to_be_bytes());
-		// self.index = index2;
-		let data = &mut self.data;
-		let bytes = &number.to_be_bytes();
-		self.index = safe_copy!(data[self.index] += [8] bytes);
+		self.write_slice(&number.to_be_bytes());
+	}
+
+	/// Writes an unsigned LEB128 variable-length integer.
+	///
+	/// See `ByteReader::read_varint`.
+	pub fn write_varint(&mut self, mut number: u64) {
+		while number >= 0x80 {
+			self.write_u8((number as u8) | 0x80);
+			number >>= 7;
+		}
+		self.write_u8(number as u8);
 	}
 
 	pub fn write_slice(&mut self, slice: &[u8]) {
-		// let len = slice.len();
-		// let index2 = self.index + len;
-		// // Method 1: resize the vector and initialize with 0s
-		// // self.data.resize(index2, 0);
-		// // Method 2: reserve space (the capacity), and sets the length
-		// // - It's safe because it guarantees the capacity, and initializes the data
-		// self.data.reserve(len);
-		// unsafe {
-		// 	self.data.set_len(index2);
-		// }
-		// self.data[self.index..index2].copy_from_slice(slice);
-		// self.index = index2;
-		let data = &mut self.data;
-		self.index = safe_copy!(data[self.index] += slice);
+		self.data.extend_from_slice(slice);
+	}
+}
+
+pub mod tests_on_bytes {
+	#[allow(unused_imports)]
+	use super::{ByteReader, PixlzrError, Reader, StreamReader, Writer};
+
+	#[test]
+	fn test_write_read() {
+		let mut writer = Writer::new();
+		writer.write_u8(1);
+		writer.write_u16(2);
+		writer.write_u32(3);
+		writer.write_f32(4.5);
+		writer.write_u64(6);
+		writer.write_f64(7.5);
+		writer.write_slice(b"end");
+		let data = writer.into_inner();
+		assert_eq!(data.len(), 1 + 2 + 4 + 4 + 8 + 8 + 3);
+
+		let mut reader = Reader::new(&data);
+		assert_eq!(reader.read_u8().unwrap(), 1);
+		assert_eq!(reader.read_u16().unwrap(), 2);
+		assert_eq!(reader.read_u32().unwrap(), 3);
+		assert_eq!(reader.read_f32().unwrap(), 4.5);
+		assert_eq!(reader.read_u64().unwrap(), 6);
+		assert_eq!(reader.read_f64().unwrap(), 7.5);
+		assert_eq!(reader.read_slice(3).unwrap(), b"end");
+		assert!(!reader.yet_readding());
+	}
+
+	#[test]
+	fn test_varint() {
+		let numbers = [0, 1, 127, 128, 300, u32::MAX as u64, u64::MAX];
+		let mut writer = Writer::new();
+		for &number in numbers.iter() {
+			writer.write_varint(number);
+		}
+		let data = writer.into_inner();
+		assert_eq!(&data[..5], &[0, 1, 127, 0x80, 1]);
+
+		let mut reader = Reader::new(&data);
+		for &number in numbers.iter() {
+			assert_eq!(reader.read_varint().unwrap(), number);
+		}
+		assert!(!reader.yet_readding());
+
+		// Too long
+		let data = [0xff; 11];
+		assert!(matches!(
+			Reader::new(&data).read_varint(),
+			Err(PixlzrError::Invalid { offset: 0, .. })
+		));
+	}
+
+	#[test]
+	fn test_cutout() {
+		let data: Vec<u8> = (0..10).collect();
+		let reader = Reader::new(&data);
+		let mut view = reader.cutout(4..8).unwrap();
+		assert_eq!(view.read_u8().unwrap(), 4);
+		assert_eq!(view.position(), 5);
+		assert_eq!(view.read_slice(3).unwrap(), &[5, 6, 7]);
+		assert!(matches!(
+			view.read_u8(),
+			Err(PixlzrError::Truncated { offset: 8 })
+		));
+		assert!(reader.cutout(8..12).is_err());
+	}
+
+	#[test]
+	fn test_stream_reader() {
+		let data: Vec<u8> = (0..10).collect();
+		let mut reader = StreamReader::new(data.as_slice());
+		assert_eq!(reader.read_u16().unwrap(), 1);
+		assert_eq!(reader.read_vec(4).unwrap(), vec![2, 3, 4, 5]);
+		assert_eq!(reader.position(), 6);
+		assert!(matches!(
+			reader.read_vec(8),
+			Err(PixlzrError::Truncated { offset: 10 })
+		));
 	}
 }
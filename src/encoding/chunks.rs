@@ -0,0 +1,175 @@
+use super::bytes::{ByteReader, Reader, Writer};
+use crate::{
+	data_types::{Metadata, Preview},
+	error::{PixlzrError, Result},
+};
+
+/// The tag of each kind of metadata chunk
+pub(crate) const CHUNK_ICC: &[u8; 4] = b"iCCP";
+pub(crate) const CHUNK_EXIF: &[u8; 4] = b"eXIf";
+pub(crate) const CHUNK_XMP: &[u8; 4] = b"iXMP";
+pub(crate) const CHUNK_TEXT: &[u8; 4] = b"tEXt";
+pub(crate) const CHUNK_PREVIEW: &[u8; 4] = b"pRVW";
+
+/// Writes the metadata, and the `preview` if any, as a chunk section: the
+/// amount of chunks, then each chunk's tag, length and data.
+pub(crate) fn write_chunks(
+	metadata: &Metadata,
+	preview: Option<&Preview>,
+	output: &mut Writer,
+) -> Result<()> {
+	let mut chunks: Vec<(&[u8; 4], Vec<u8>)> = vec![];
+	// The preview comes first, so that it's found sooner
+	if let Some(preview) =
+		preview.filter(|preview| !preview.data.is_empty())
+	{
+		let data = qoi::encode_to_vec(
+			&preview.data,
+			preview.width,
+			preview.height,
+		)?;
+		chunks.push((CHUNK_PREVIEW, data));
+	}
+	for (tag, data) in [
+		(CHUNK_ICC, &metadata.icc),
+		(CHUNK_EXIF, &metadata.exif),
+		(CHUNK_XMP, &metadata.xmp),
+	] {
+		if let Some(data) = data {
+			chunks.push((tag, data.clone()));
+		}
+	}
+	// A text chunk is its key's length, its key, then its value
+	for (key, value) in metadata.text.iter() {
+		let mut data = Writer::with_capacity(key.len() + value.len() + 2);
+		data.write_varint(key.len() as u64);
+		data.write_slice(key.as_bytes());
+		data.write_slice(value.as_bytes());
+		chunks.push((CHUNK_TEXT, data.into_inner()));
+	}
+
+	output.write_varint(chunks.len() as u64);
+	for (tag, data) in chunks {
+		output.write_slice(tag);
+		output.write_varint(data.len() as u64);
+		output.write_slice(&data);
+	}
+	Ok(())
+}
+
+/// Reads a chunk section, into the metadata and the preview, skipping the
+/// chunks of unknown tags.
+pub(crate) fn read_chunks<B: ByteReader>(
+	reader: &mut B,
+) -> Result<(Metadata, Option<Preview>)> {
+	let mut metadata = Metadata::default();
+	let mut preview = None;
+	let count = reader.read_varint()?;
+	for _ in 0..count {
+		let tag = reader.read_array::<4>()?;
+		let len = reader.read_varint_usize()?;
+		let offset = reader.position();
+		let data = reader.read_vec(len)?;
+		match &tag {
+			CHUNK_ICC => metadata.icc = Some(data),
+			CHUNK_EXIF => metadata.exif = Some(data),
+			CHUNK_XMP => metadata.xmp = Some(data),
+			CHUNK_TEXT => metadata.text.push(read_text(&data, offset)?),
+			CHUNK_PREVIEW => preview = Some(read_preview(&data, offset)?),
+			_ => {}
+		}
+	}
+	Ok((metadata, preview))
+}
+
+/// Decodes the QOI image of a preview chunk, found at `offset`
+fn read_preview(data: &[u8], offset: usize) -> Result<Preview> {
+	let (header, data) = qoi::decode_to_vec(data)?;
+	if header.channels != qoi::Channels::Rgba {
+		return Err(PixlzrError::Invalid {
+			offset,
+			reason: "the preview must be RGBA",
+		});
+	}
+	Ok(Preview {
+		width: header.width,
+		height: header.height,
+		data,
+	})
+}
+
+/// Splits the data of a text chunk, found at `offset`, into its key and
+/// value
+fn read_text(data: &[u8], offset: usize) -> Result<(String, String)> {
+	let mut reader = Reader::with_offset(data, offset);
+	let key_len = reader.read_varint_usize()?;
+	let key = reader.read_slice(key_len)?;
+	let value = reader.read_slice(reader.remaining())?;
+	let invalid = |_| PixlzrError::Invalid {
+		offset,
+		reason: "the text chunk isn't valid UTF-8",
+	};
+	Ok((
+		String::from_utf8(key.to_vec()).map_err(invalid)?,
+		String::from_utf8(value.to_vec()).map_err(invalid)?,
+	))
+}
+
+pub mod tests_on_chunks {
+	#[allow(unused_imports)]
+	use super::{read_chunks, write_chunks, CHUNK_TEXT};
+	#[allow(unused_imports)]
+	use crate::{
+		data_types::{Metadata, Preview},
+		encoding::bytes::{Reader, Writer},
+		error::PixlzrError,
+	};
+
+	#[test]
+	fn test_metadata_round_trip() {
+		let mut metadata = Metadata {
+			icc: Some(vec![1, 2, 3]),
+			exif: Some(b"MM\0*".to_vec()),
+			xmp: None,
+			text: vec![],
+		};
+		metadata.push_text("Author", "Zalu");
+		metadata.push_text("Comment", "çà ✓");
+
+		let preview = Preview {
+			width: 2,
+			height: 1,
+			data: vec![255, 0, 0, 255, 0, 0, 255, 128],
+		};
+
+		let mut output = Writer::new();
+		write_chunks(&metadata, Some(&preview), &mut output).unwrap();
+		let data = output.into_inner();
+		let mut reader = Reader::new(&data);
+		assert_eq!(
+			read_chunks(&mut reader).unwrap(),
+			(metadata.clone(), Some(preview))
+		);
+		assert!(!reader.yet_readding());
+		assert_eq!(metadata.get_text("Comment"), Some("çà ✓"));
+	}
+
+	#[test]
+	fn test_metadata_skips_unknown() {
+		let mut output = Writer::new();
+		output.write_varint(2);
+		output.write_slice(b"zzzz");
+		output.write_varint(2);
+		output.write_slice(&[9, 9]);
+		output.write_slice(CHUNK_TEXT);
+		output.write_varint(3);
+		output.write_slice(&[1, b'k', 0xff]);
+		let data = output.into_inner();
+
+		// The unknown chunk is skipped, and the value isn't UTF-8
+		assert!(matches!(
+			read_chunks(&mut Reader::new(&data)),
+			Err(PixlzrError::Invalid { offset: 13, .. })
+		));
+	}
+}
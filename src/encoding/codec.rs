@@ -0,0 +1,619 @@
+use super::{
+	bytes::{ByteReader, Reader},
+	Raw,
+};
+use crate::{
+	constants::*,
+	data_types::{ColorType, PixlzrBlock, RawImage, SampleType},
+	error::{PixlzrError, Result},
+};
+
+use qoi;
+
+/// The encoding of a block's payload, written before it.
+///
+/// See `encode_payload` for the payloads of the "compact" layout. Before
+/// it, the payloads were:
+/// - `Qoi`: the QOI image, without its magic numbers
+/// - `Raw`: `width: u32`, `height: u32`, `channels: u8`, and the pixels
+/// - `Solid`: `width: u32`, `height: u32`, `channels: u8`, and a single
+///   pixel, repeated over the whole block
+///
+/// `Reference` has no payload: the block is the same as the one at its
+/// place in the previous frame of a sequence.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum BlockCodec {
+	#[default]
+	Qoi = 0,
+	Raw = 1,
+	Solid = 2,
+	Delta = 3,
+	Reference = 4,
+}
+
+impl BlockCodec {
+	pub fn from_u8(value: u8) -> Option<Self> {
+		match value {
+			0 => Some(BlockCodec::Qoi),
+			1 => Some(BlockCodec::Raw),
+			2 => Some(BlockCodec::Solid),
+			3 => Some(BlockCodec::Delta),
+			4 => Some(BlockCodec::Reference),
+			_ => None,
+		}
+	}
+}
+
+/// The scale code of a block whose dimensions are written explicitly
+pub const SCALE_CODE_EXPLICIT: u8 = 0xff;
+/// The biggest downscale level that fits in a scale code
+const MAX_SCALE_LEVEL: u8 = 14;
+
+/// The size of a block side of `full` pixels, after being downscaled by
+/// `2 ^ -level`, as done by `operations::reduce_image_section`.
+#[inline]
+pub fn scaled_size(full: u32, level: u8) -> u32 {
+	(full as f64 * (-(level as f64)).exp2()).max(1f64).ceil() as u32
+}
+
+/// The scale code of a block of `dimensions`, inside a frame of `full`
+/// dimensions in the block grid.
+///
+/// The high nibble has the horizontal downscale level, and the low nibble
+/// the vertical one. `None` if the dimensions are not a power-of-two
+/// downscale of the frame.
+pub fn scale_code(full: (u32, u32), dimensions: (u32, u32)) -> Option<u8> {
+	let level = |full: u32, size: u32| {
+		(0..=MAX_SCALE_LEVEL)
+			.find(|&level| scaled_size(full, level) == size)
+	};
+	Some(level(full.0, dimensions.0)? << 4 | level(full.1, dimensions.1)?)
+}
+
+/// The dimensions of a block of scale `code`, inside a frame of `full`
+/// dimensions in the block grid. `None` for `SCALE_CODE_EXPLICIT`.
+pub fn scale_from_code(full: (u32, u32), code: u8) -> Option<(u32, u32)> {
+	if code == SCALE_CODE_EXPLICIT {
+		return None;
+	}
+	Some((
+		scaled_size(full.0, code >> 4),
+		scaled_size(full.1, code & 0xf),
+	))
+}
+
+/// Encodes the block's pixels with the codec that produces the smallest
+/// payload.
+///
+/// The payloads don't carry the block's dimensions nor its color type,
+/// which are written around them in the "compact" layout:
+/// - `Qoi`: the QOI image, without its header
+/// - `Raw`: the pixels
+/// - `Solid`: a single pixel
+/// - `Delta`: the difference of each sample to the one on its left (or
+///   above it, on the first column)
+///
+/// Samples wider than 8 bits are written in little endian. As QOI is
+/// 8 bits only, their blocks are encoded with `Delta`, which leaves the
+/// pixels as compressible as possible for the line compression.
+pub(crate) fn encode_payload(
+	block: &PixlzrBlock,
+) -> Result<(BlockCodec, Raw)> {
+	let data = block.as_slice();
+	let color = block.color();
+	let sample_size = color.sample_type().size();
+
+	// A block of a single color is always the smallest
+	let mut pixels = block.pixels();
+	if let Some(first) = pixels.next() {
+		if pixels.all(|pixel| pixel == first) {
+			let mut pixel = first.to_vec();
+			swap_to_le(&mut pixel, sample_size);
+			return Ok((BlockCodec::Solid, pixel));
+		}
+	}
+
+	if color.sample_type() != SampleType::U8 {
+		let delta = delta_encode(data, block.width() as usize, color);
+		return Ok((BlockCodec::Delta, delta));
+	}
+
+	let qoi = encode_qoi(block)?;
+	if data.len() < qoi.len() {
+		Ok((BlockCodec::Raw, data.to_vec()))
+	} else {
+		Ok((BlockCodec::Qoi, qoi))
+	}
+}
+
+/// Encodes the block in the QOI format, without its header.
+///
+/// As QOI only has RGB(A) images, gray blocks are encoded as RGB(A).
+pub(crate) fn encode_qoi(block: &PixlzrBlock) -> Result<Raw> {
+	let (width, height) = block.dimensions();
+	let color = block.color();
+	let expanded;
+	let data = if color.is_gray() {
+		expanded = gray_to_rgb(block.as_slice(), color);
+		expanded.as_slice()
+	} else {
+		block.as_slice()
+	};
+	let mut encoded =
+		qoi::Encoder::new(&data, width, height)?.encode_to_vec()?;
+	// Discards the QOI header
+	encoded.drain(..QOI_HEADER_SIZE);
+	Ok(encoded)
+}
+
+/// Swaps the octets of each sample of `size` octets between the native
+/// and little endianness
+#[inline]
+fn swap_to_le(data: &mut [u8], size: usize) {
+	if cfg!(target_endian = "big") && size > 1 {
+		data.chunks_exact_mut(size).for_each(<[u8]>::reverse);
+	}
+}
+
+/// The samples of `data` (in the native endianness) as `u32`s
+fn read_samples(data: &[u8], size: usize) -> Vec<u32> {
+	data.chunks_exact(size)
+		.map(|sample| {
+			let mut value = [0; 4];
+			value[..size].copy_from_slice(sample);
+			if cfg!(target_endian = "big") {
+				u32::from_be_bytes(value) >> (8 * (4 - size))
+			} else {
+				u32::from_le_bytes(value)
+			}
+		})
+		.collect()
+}
+
+/// The index of the sample used to predict the sample at `index`: the one
+/// on its left, or above it on the first column
+#[inline]
+fn predictor(
+	index: usize,
+	row_len: usize,
+	channels: usize,
+) -> Option<usize> {
+	if index % row_len >= channels {
+		Some(index - channels)
+	} else {
+		index.checked_sub(row_len)
+	}
+}
+
+/// Encodes the samples of `data`, of `width` pixels per row, as the
+/// wrapping difference to their predictor, in little endian
+fn delta_encode(data: &[u8], width: usize, color: ColorType) -> Raw {
+	let size = color.sample_type().size();
+	let channels = color.channels();
+	let samples = read_samples(data, size);
+	let row_len = width * channels;
+	let mut output = Vec::with_capacity(data.len());
+	for (index, &sample) in samples.iter().enumerate() {
+		let predicted =
+			predictor(index, row_len, channels).map_or(0, |i| samples[i]);
+		let delta = sample.wrapping_sub(predicted);
+		output.extend_from_slice(&delta.to_le_bytes()[..size]);
+	}
+	output
+}
+
+/// Decodes the samples encoded by `delta_encode`, into the native
+/// endianness
+fn delta_decode(payload: &[u8], width: usize, color: ColorType) -> Raw {
+	let size = color.sample_type().size();
+	let channels = color.channels();
+	let mask = u32::MAX >> (8 * (4 - size));
+	let row_len = width * channels;
+	let mut samples: Vec<u32> = Vec::with_capacity(payload.len() / size);
+	for (index, delta) in payload.chunks_exact(size).enumerate() {
+		let mut value = [0; 4];
+		value[..size].copy_from_slice(delta);
+		let predicted =
+			predictor(index, row_len, channels).map_or(0, |i| samples[i]);
+		samples.push(
+			u32::from_le_bytes(value).wrapping_add(predicted) & mask,
+		);
+	}
+	let mut output = Vec::with_capacity(payload.len());
+	for sample in samples {
+		output.extend_from_slice(&sample.to_le_bytes()[..size]);
+	}
+	swap_to_le(&mut output, size);
+	output
+}
+
+/// Expands the pixels of a gray `color` into RGB(A)
+fn gray_to_rgb(data: &[u8], color: ColorType) -> Raw {
+	match color {
+		ColorType::L8 => data.iter().flat_map(|&l| [l, l, l]).collect(),
+		ColorType::La8 => data
+			.chunks_exact(2)
+			.flat_map(|px| [px[0], px[0], px[0], px[1]])
+			.collect(),
+		_ => data.to_vec(),
+	}
+}
+
+/// Collapses RGB(A) pixels, expanded by `gray_to_rgb`, into a gray
+/// `color`
+fn rgb_to_gray(data: &[u8], color: ColorType) -> Raw {
+	match color {
+		ColorType::L8 => data.iter().step_by(3).copied().collect(),
+		ColorType::La8 => {
+			data.chunks_exact(4).flat_map(|px| [px[0], px[3]]).collect()
+		}
+		_ => data.to_vec(),
+	}
+}
+
+/// Decodes a "compact" payload of the given `codec`, for a block of
+/// `width` x `height` pixels of the color type `color_code`.
+pub(crate) fn decode_compact_payload(
+	codec: BlockCodec,
+	(width, height): (u32, u32),
+	color_code: u8,
+	payload: &[u8],
+	offset: usize,
+) -> Result<RawImage> {
+	let color =
+		ColorType::from_u8(color_code).ok_or(PixlzrError::Invalid {
+			offset,
+			reason: "unknown block color type",
+		})?;
+	let too_large = || PixlzrError::Invalid {
+		offset,
+		reason: "the block is too large",
+	};
+	let pixel_count = (width as usize)
+		.checked_mul(height as usize)
+		.ok_or_else(too_large)?;
+	let sample_size = color.sample_type().size();
+	let len = pixel_count
+		.checked_mul(color.bytes_per_pixel())
+		.filter(|&len| len <= PIXLZR_MAX_BLOCK_OCTETS)
+		.ok_or_else(too_large)?;
+	let data = match codec {
+		BlockCodec::Qoi if sample_size == 1 => {
+			// Gray blocks were encoded as RGB(A)
+			let qoi_channels = 3 + color.has_alpha() as u8;
+			// Rebuilds the QOI header
+			let mut data =
+				Vec::with_capacity(QOI_HEADER_SIZE + payload.len());
+			data.extend_from_slice(QOI_MAGIC);
+			data.extend_from_slice(&width.to_be_bytes());
+			data.extend_from_slice(&height.to_be_bytes());
+			data.push(qoi_channels);
+			data.push(0); // sRGB with linear alpha
+			data.extend_from_slice(payload);
+			let (qoi_header, qoi_data) = qoi::decode_to_vec(data)?;
+			if qoi_header.channels as u8 != qoi_channels {
+				return Err(PixlzrError::Invalid {
+					offset,
+					reason: "the QOI block doesn't match its channels",
+				});
+			}
+			if color.is_gray() {
+				rgb_to_gray(&qoi_data, color)
+			} else {
+				qoi_data
+			}
+		}
+		BlockCodec::Solid if payload.len() == color.bytes_per_pixel() => {
+			let mut data = payload.repeat(pixel_count);
+			swap_to_le(&mut data, sample_size);
+			data
+		}
+		BlockCodec::Raw if payload.len() == len => {
+			let mut data = payload.to_vec();
+			swap_to_le(&mut data, sample_size);
+			data
+		}
+		BlockCodec::Delta if payload.len() == len => {
+			delta_decode(payload, width as usize, color)
+		}
+		_ => {
+			return Err(PixlzrError::Invalid {
+				offset,
+				reason: "the pixels don't match the block's size",
+			})
+		}
+	};
+	Ok(RawImage { color, data })
+}
+
+/// Fails if a block of the `dimensions` is larger than its `frame`
+fn check_frame(
+	(width, height): (u32, u32),
+	frame: (u32, u32),
+	offset: usize,
+) -> Result<()> {
+	if width > frame.0 || height > frame.1 {
+		return Err(PixlzrError::Invalid {
+			offset,
+			reason: "the block is larger than its cell",
+		});
+	}
+	Ok(())
+}
+
+/// Decodes a payload of the given `codec`, as written before the "compact"
+/// resource, into the block's width, height and pixels, which can't be
+/// larger than its `frame`.
+///
+/// `reader` must span the whole payload.
+pub(crate) fn decode_payload(
+	codec: BlockCodec,
+	reader: &mut Reader,
+	frame: (u32, u32),
+) -> Result<(u32, u32, RawImage)> {
+	match codec {
+		BlockCodec::Qoi => {
+			let offset = reader.position();
+			decode_qoi(
+				reader.read_slice(reader.remaining())?,
+				frame,
+				offset,
+			)
+		}
+		BlockCodec::Delta | BlockCodec::Reference => {
+			Err(PixlzrError::Invalid {
+				offset: reader.position(),
+				reason: "the codec needs the compact layout",
+			})
+		}
+		BlockCodec::Raw | BlockCodec::Solid => {
+			let offset = reader.position();
+			let width = reader.read_u32()?;
+			let height = reader.read_u32()?;
+			check_frame((width, height), frame, offset)?;
+			let offset = reader.position();
+			let channels = reader.read_slice(1)?[0] as usize;
+			if channels != 3 && channels != 4 {
+				return Err(PixlzrError::Invalid {
+					offset,
+					reason: "a block must have 3 or 4 channels",
+				});
+			}
+
+			let too_large = || PixlzrError::Invalid {
+				offset,
+				reason: "the block is too large",
+			};
+			let pixel_count = (width as usize)
+				.checked_mul(height as usize)
+				.ok_or_else(too_large)?;
+			let len = pixel_count
+				.checked_mul(channels)
+				.filter(|&len| len <= PIXLZR_MAX_BLOCK_OCTETS)
+				.ok_or_else(too_large)?;
+			let offset = reader.position();
+			let pixels = reader.read_slice(reader.remaining())?;
+			let data = match codec {
+				BlockCodec::Solid if pixels.len() == channels => {
+					pixels.repeat(pixel_count)
+				}
+				BlockCodec::Raw if pixels.len() == len => pixels.to_vec(),
+				_ => {
+					return Err(PixlzrError::Invalid {
+						offset,
+						reason: "the pixels don't match the block's size",
+					})
+				}
+			};
+
+			Ok((
+				width,
+				height,
+				RawImage {
+					color: if channels == 4 {
+						ColorType::Rgba8
+					} else {
+						ColorType::Rgb8
+					},
+					data,
+				},
+			))
+		}
+	}
+}
+
+/// Decodes a QOI image, without its magic numbers
+pub(crate) fn decode_qoi(
+	payload: &[u8],
+	frame: (u32, u32),
+	offset: usize,
+) -> Result<(u32, u32, RawImage)> {
+	// Copying the payload next to the magic numbers is cheaper than
+	// decoding it through `qoi::Decoder::from_stream`
+	let encoded = {
+		let mut data = QOI_MAGIC.to_vec();
+		data.extend_from_slice(payload);
+		data
+	};
+	// Checks the dimensions before allocating the pixels
+	let qoi_header = qoi::decode_header(&encoded)?;
+	check_frame((qoi_header.width, qoi_header.height), frame, offset)?;
+
+	// Decodes QOI block
+	let (qoi_header, qoi_data) = qoi::decode_to_vec(encoded)?;
+
+	Ok((
+		qoi_header.width,
+		qoi_header.height,
+		RawImage {
+			color: if qoi_header.channels.is_rgba() {
+				ColorType::Rgba8
+			} else {
+				ColorType::Rgb8
+			},
+			data: qoi_data,
+		},
+	))
+}
+
+pub mod tests_on_codec {
+	#[allow(unused_imports)]
+	use super::{
+		decode_compact_payload, decode_payload, encode_payload,
+		scale_code, scale_from_code, BlockCodec, Reader,
+	};
+	#[allow(unused_imports)]
+	use crate::data_types::{
+		ColorType, PixlzrBlock, PixlzrBlockRaw, RawImage,
+	};
+	#[allow(unused_imports)]
+	use crate::error::PixlzrError;
+
+	#[allow(dead_code)]
+	fn block(width: u32, height: u32, data: Vec<u8>) -> PixlzrBlock {
+		colored_block(width, height, ColorType::Rgb8, data)
+	}
+
+	#[allow(dead_code)]
+	fn colored_block(
+		width: u32,
+		height: u32,
+		color: ColorType,
+		data: Vec<u8>,
+	) -> PixlzrBlock {
+		PixlzrBlockRaw {
+			width,
+			height,
+			block_value: None,
+			axis_values: None,
+			data: RawImage { color, data },
+		}
+		.into()
+	}
+
+	#[allow(dead_code)]
+	fn round_trip(block: &PixlzrBlock) -> BlockCodec {
+		let (codec, payload) = encode_payload(block).unwrap();
+		let data = decode_compact_payload(
+			codec,
+			block.dimensions(),
+			block.color() as u8,
+			&payload,
+			0,
+		)
+		.unwrap();
+		assert_eq!(data.color, block.color());
+		assert_eq!(data.data, block.as_slice());
+		codec
+	}
+
+	#[test]
+	fn test_solid_block() {
+		let solid = block(8, 4, [10, 20, 30].repeat(8 * 4));
+		assert_eq!(round_trip(&solid), BlockCodec::Solid);
+		let single = block(1, 1, vec![1, 2, 3]);
+		assert_eq!(round_trip(&single), BlockCodec::Solid);
+	}
+
+	#[test]
+	fn test_raw_block() {
+		// Noise doesn't compress, so the raw pixels are smaller
+		let data = (0..4 * 4 * 3).map(|i| (i * 97 % 251) as u8).collect();
+		assert_eq!(round_trip(&block(4, 4, data)), BlockCodec::Raw);
+	}
+
+	#[test]
+	fn test_qoi_block() {
+		let data =
+			(0..32 * 32).flat_map(|i| [(i / 64) as u8; 3]).collect();
+		assert_eq!(round_trip(&block(32, 32, data)), BlockCodec::Qoi);
+	}
+
+	#[test]
+	fn test_gray_blocks() {
+		let data: Vec<u8> = (0..32 * 32).map(|i| (i / 64) as u8).collect();
+		let gray = colored_block(32, 32, ColorType::L8, data.clone());
+		assert_eq!(round_trip(&gray), BlockCodec::Qoi);
+
+		let data = data.iter().flat_map(|&l| [l, 255 - l]).collect();
+		let gray_alpha = colored_block(32, 32, ColorType::La8, data);
+		assert_eq!(round_trip(&gray_alpha), BlockCodec::Qoi);
+
+		let solid =
+			colored_block(4, 4, ColorType::La8, [9, 99].repeat(16));
+		assert_eq!(round_trip(&solid), BlockCodec::Solid);
+	}
+
+	#[test]
+	fn test_wide_blocks() {
+		// 16 bits gray, as a gradient
+		let data = (0..16 * 8u32)
+			.flat_map(|i| ((i * 300) as u16).to_ne_bytes())
+			.collect();
+		let gray = colored_block(16, 8, ColorType::L16, data);
+		assert_eq!(round_trip(&gray), BlockCodec::Delta);
+
+		// Floats, with alpha
+		let data = (0..8 * 8)
+			.flat_map(|i| [i as f32 * 0.7, -1.5, 1e9, 0.5])
+			.flat_map(f32::to_ne_bytes)
+			.collect();
+		let hdr = colored_block(8, 8, ColorType::Rgba32F, data);
+		assert_eq!(round_trip(&hdr), BlockCodec::Delta);
+
+		let data = [1000u16, 2000, 3000].repeat(16);
+		let data = data.into_iter().flat_map(u16::to_ne_bytes).collect();
+		let solid = colored_block(4, 4, ColorType::Rgb16, data);
+		assert_eq!(round_trip(&solid), BlockCodec::Solid);
+	}
+
+	#[test]
+	fn test_block_too_large() {
+		// Overflowing, or beyond the limit of a decoded block
+		for dimensions in [(u32::MAX, u32::MAX), (60_000, 60_000)] {
+			let res = decode_compact_payload(
+				BlockCodec::Solid,
+				dimensions,
+				ColorType::Rgba32F as u8,
+				&[0; 16],
+				0,
+			);
+			assert!(matches!(res, Err(PixlzrError::Invalid { .. })));
+		}
+
+		// Legacy solid blocks, larger than their cell, overflowing, or
+		// beyond the limit
+		for (side, frame) in [
+			(u32::MAX, (16, 16)),
+			(u32::MAX, (u32::MAX, u32::MAX)),
+			(60_000, (60_000, 60_000)),
+		] {
+			let payload: Vec<u8> = [side.to_be_bytes(); 2]
+				.concat()
+				.into_iter()
+				.chain([4, 1, 2, 3, 4])
+				.collect();
+			let res = decode_payload(
+				BlockCodec::Solid,
+				&mut Reader::new(&payload),
+				frame,
+			);
+			assert!(matches!(res, Err(PixlzrError::Invalid { .. })));
+		}
+	}
+
+	#[test]
+	fn test_scale_code() {
+		assert_eq!(scale_code((64, 64), (64, 64)), Some(0x00));
+		assert_eq!(scale_code((64, 64), (16, 32)), Some(0x21));
+		assert_eq!(scale_code((64, 64), (1, 1)), Some(0x66));
+		assert_eq!(scale_code((20, 64), (3, 8)), Some(0x33));
+		assert_eq!(scale_code((64, 64), (48, 64)), None);
+		assert_eq!(scale_code((64, 64), (128, 64)), None);
+
+		assert_eq!(scale_from_code((64, 64), 0x21), Some((16, 32)));
+		assert_eq!(scale_from_code((20, 64), 0x33), Some((3, 8)));
+		assert_eq!(scale_from_code((64, 64), 0xff), None);
+	}
+}
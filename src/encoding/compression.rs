@@ -0,0 +1,121 @@
+use super::Raw;
+use crate::{
+	data_types::Compression,
+	error::{PixlzrError, Result},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+use std::io::{Read, Write};
+
+/// Compresses a line of encoded blocks.
+///
+/// Returns `None` if the compressed line isn't smaller than `line`.
+pub(crate) fn compress_line(
+	line: &[u8],
+	compression: Compression,
+) -> Result<Option<Raw>> {
+	let compressed = match compression {
+		Compression::None => return Ok(None),
+		Compression::Deflate(level) => {
+			let mut encoder = DeflateEncoder::new(
+				Vec::with_capacity(line.len()),
+				flate2::Compression::new(level.min(9)),
+			);
+			encoder.write_all(line)?;
+			encoder.finish()?
+		}
+		Compression::Zstd(level) => zstd::bulk::compress(line, level)?,
+	};
+	Ok((compressed.len() < line.len()).then_some(compressed))
+}
+
+/// Decompresses a line of encoded blocks, found at `offset`, failing if it
+/// inflates beyond `max_len` octets
+pub(crate) fn decompress_line(
+	line: &[u8],
+	compression: Compression,
+	offset: usize,
+	max_len: usize,
+) -> Result<Raw> {
+	let mut output = Vec::with_capacity((line.len() * 2).min(max_len));
+	// Reads one octet past the limit, to tell if it's reached
+	let limit = (max_len as u64).saturating_add(1);
+	let res = match compression {
+		Compression::None => {
+			return Err(PixlzrError::Invalid {
+				offset,
+				reason: "compressed line without a compression method",
+			})
+		}
+		Compression::Deflate(_) => DeflateDecoder::new(line)
+			.take(limit)
+			.read_to_end(&mut output),
+		Compression::Zstd(_) => {
+			zstd::stream::Decoder::new(line).and_then(|decoder| {
+				decoder.take(limit).read_to_end(&mut output)
+			})
+		}
+	};
+	res.map_err(|_| PixlzrError::Invalid {
+		offset,
+		reason: "the compressed line is corrupted",
+	})?;
+	if output.len() > max_len {
+		return Err(PixlzrError::Invalid {
+			offset,
+			reason: "the compressed line inflates beyond its blocks",
+		});
+	}
+	Ok(output)
+}
+
+pub mod tests_on_compression {
+	#[allow(unused_imports)]
+	use super::{compress_line, decompress_line, Compression};
+
+	#[allow(dead_code)]
+	fn sample_line() -> Vec<u8> {
+		(0..4096u32).map(|i| (i / 64) as u8).collect()
+	}
+
+	#[test]
+	fn test_compress_decompress() {
+		let line = sample_line();
+		for compression in [Compression::Deflate(9), Compression::Zstd(3)]
+		{
+			let compressed =
+				compress_line(&line, compression).unwrap().unwrap();
+			assert!(compressed.len() < line.len());
+			let decompressed =
+				decompress_line(&compressed, compression, 0, line.len())
+					.unwrap();
+			assert_eq!(decompressed, line);
+		}
+	}
+
+	#[test]
+	fn test_decompress_limit() {
+		// A line inflating beyond the largest one its blocks can take
+		let line = vec![0; 1 << 20];
+		for compression in [Compression::Deflate(9), Compression::Zstd(3)]
+		{
+			let compressed =
+				compress_line(&line, compression).unwrap().unwrap();
+			assert!(compressed.len() < 1 << 12);
+			assert!(decompress_line(&compressed, compression, 0, 1 << 12)
+				.is_err());
+		}
+	}
+
+	#[test]
+	fn test_compress_keeps_smaller() {
+		// Too short to be compressed
+		let line = [1, 2, 3];
+		assert!(compress_line(&line, Compression::Zstd(3))
+			.unwrap()
+			.is_none());
+		assert!(compress_line(&line, Compression::None)
+			.unwrap()
+			.is_none());
+	}
+}
@@ -0,0 +1,347 @@
+use super::{
+	bytes::{ByteReader, Reader},
+	decode_header, decode_row, Header, Line,
+};
+use crate::{
+	data_types::{
+		ColorType, Pixlzr, PixlzrBlock, PixlzrBlockRaw, RawImage,
+		SampleType,
+	},
+	error::{PixlzrError, Result},
+};
+
+use crc32fast::Hasher;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// The gray level of the blocks that replace a damaged line
+const DAMAGED_LINE_FILL: u8 = 128;
+
+/// A `ByteReader` that keeps the CRC32 of every octet read through it.
+pub(crate) struct CrcReader<'a, B: ByteReader> {
+	inner: &'a mut B,
+	hasher: Hasher,
+}
+
+impl<'a, B: ByteReader> CrcReader<'a, B> {
+	pub fn new(inner: &'a mut B) -> Self {
+		Self {
+			inner,
+			hasher: Hasher::new(),
+		}
+	}
+
+	/// The CRC32 of the octets read so far.
+	pub fn checksum(&self) -> u32 {
+		self.hasher.clone().finalize()
+	}
+
+	/// The underlying reader, to read octets that are not hashed.
+	pub fn inner(&mut self) -> &mut B {
+		self.inner
+	}
+}
+
+impl<B: ByteReader> ByteReader for CrcReader<'_, B> {
+	fn position(&self) -> usize {
+		self.inner.position()
+	}
+
+	fn read_into(&mut self, buf: &mut [u8]) -> Result<()> {
+		self.inner.read_into(buf)?;
+		self.hasher.update(buf);
+		Ok(())
+	}
+
+	fn read_vec(&mut self, len: usize) -> Result<Vec<u8>> {
+		let buf = self.inner.read_vec(len)?;
+		self.hasher.update(&buf);
+		Ok(buf)
+	}
+}
+
+impl Header<'_> {
+	/// Checks the line of blocks at `row`, found in `view`, against its
+	/// checksum, if the version has them
+	pub fn check_line(&self, row: usize, view: &Reader) -> Result<()> {
+		let Some(&expected) = self.line_checksums.get(row) else {
+			return Ok(());
+		};
+		let mut view = *view;
+		if crc32fast::hash(view.read_slice(view.remaining())?) != expected
+		{
+			let (start, end) = self.line_position(row)?;
+			return Err(PixlzrError::ChecksumMismatch { start, end });
+		}
+		Ok(())
+	}
+}
+
+/// A gray, opaque pixel of the `color` type, of the `DAMAGED_LINE_FILL`
+/// level
+fn fill_pixel(color: ColorType) -> Vec<u8> {
+	let (gray, opaque) = match color.sample_type() {
+		SampleType::U8 => (vec![DAMAGED_LINE_FILL], vec![u8::MAX]),
+		SampleType::U16 => (
+			((DAMAGED_LINE_FILL as u16) << 8).to_ne_bytes().to_vec(),
+			u16::MAX.to_ne_bytes().to_vec(),
+		),
+		SampleType::F32 => (
+			(DAMAGED_LINE_FILL as f32 / 255.).to_ne_bytes().to_vec(),
+			1f32.to_ne_bytes().to_vec(),
+		),
+	};
+	let samples = color.channels() - color.has_alpha() as usize;
+	let mut pixel = gray.repeat(samples);
+	if color.has_alpha() {
+		pixel.extend(opaque);
+	}
+	pixel
+}
+
+/// A line of flat blocks of the `color` type, one per cell, standing for
+/// the damaged line of blocks at `row`.
+///
+/// On a tree layout, each of them is a quadtree of a single block.
+fn flat_line(header: &Header, row: usize, color: ColorType) -> Line {
+	(0..header.cols())
+		.map(|col| {
+			let block: PixlzrBlock = PixlzrBlockRaw {
+				width: 1,
+				height: 1,
+				block_value: Some(0.),
+				axis_values: None,
+				data: RawImage {
+					color,
+					data: fill_pixel(color),
+				},
+			}
+			.into();
+			(block, header.cell(col, row))
+		})
+		.collect()
+}
+
+impl Pixlzr {
+	/// Checks the integrity of a PIXLZR image, returning the index of each
+	/// damaged line of blocks.
+	///
+	/// A line is damaged if it doesn't match its checksum, can't be
+	/// decoded, or is missing from the data. For versions before checksums,
+	/// only the last two can be found.
+	///
+	/// Fails with a `PixlzrError` if the header itself is damaged.
+	pub fn verify(inp: &[u8]) -> Result<Vec<usize>> {
+		let mut reader = Reader::new(inp);
+		let header = decode_header(&mut reader)?;
+		Ok((0..header.rows())
+			.into_par_iter()
+			.filter(|&row| decode_row(&reader, &header, row).is_err())
+			.collect())
+	}
+
+	/// Decodes the PIXLZR image from a slice of bytes, like
+	/// `decode_from_slice`, but replacing each damaged line of blocks with
+	/// flat gray blocks, instead of failing.
+	///
+	/// The gray blocks take the color type of the first intact block, or
+	/// are grayscale if there is none. On a tree layout, each damaged cell
+	/// becomes a single block.
+	///
+	/// See `verify` for what makes a line damaged.
+	/// Fails with a `PixlzrError` if the header itself is damaged.
+	pub fn decode_lenient(inp: &[u8]) -> Result<Self> {
+		let mut reader = Reader::new(inp);
+		let header = decode_header(&mut reader)?;
+
+		let lines: Vec<_> = (0..header.rows())
+			.into_par_iter()
+			.map(|row| decode_row(&reader, &header, row))
+			.collect();
+		// The color of the image, as far as the intact lines tell
+		let color = lines
+			.iter()
+			.flatten()
+			.find_map(|line| line.first())
+			.map_or(ColorType::L8, |(block, _)| block.color());
+
+		let blocks: Vec<_> = lines
+			.into_iter()
+			.enumerate()
+			.flat_map(|(row, line)| {
+				line.unwrap_or_else(|_| flat_line(&header, row, color))
+			})
+			.collect();
+
+		Ok(header.into_pixlzr(blocks))
+	}
+}
+
+pub mod tests_on_integrity {
+	#[allow(unused_imports)]
+	use super::{Pixlzr, PixlzrError, DAMAGED_LINE_FILL};
+	#[allow(unused_imports)]
+	use crate::data_types::{
+		test_images::noise, BlockRect, ColorType, Compression, FilterType,
+	};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, GenericImageView, RgbImage, RgbaImage};
+
+	#[allow(dead_code)]
+	fn sample_data(compression: Compression) -> (Pixlzr, Vec<u8>) {
+		let img = RgbImage::from_fn(40, 40, |x, y| {
+			image::Rgb([(x * 6) as u8, (y * 6) as u8, (x ^ y) as u8])
+		});
+		let mut pix =
+			Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16);
+		pix.compression = compression;
+		let data = pix.encode_to_vec().unwrap();
+		(pix, data)
+	}
+
+	#[test]
+	fn test_verify() {
+		let (_, mut data) = sample_data(Compression::None);
+		assert_eq!(Pixlzr::verify(&data).unwrap(), Vec::<usize>::new());
+
+		// Flips a bit on the middle line of blocks
+		let (start, end) = {
+			let mut reader = super::Reader::new(&data);
+			super::decode_header(&mut reader).unwrap().line_positions[1]
+		};
+		data[(start + end) / 2] ^= 0x10;
+		assert_eq!(Pixlzr::verify(&data).unwrap(), vec![1]);
+		assert!(matches!(
+			Pixlzr::decode_from_slice(&data),
+			Err(PixlzrError::ChecksumMismatch { .. })
+		));
+		assert!(matches!(
+			Pixlzr::read_from(data.as_slice()),
+			Err(PixlzrError::ChecksumMismatch { .. })
+		));
+
+		// The last line is missing
+		data.truncate(end + 3);
+		assert_eq!(Pixlzr::verify(&data).unwrap(), vec![1, 2]);
+	}
+
+	#[test]
+	fn test_verify_header() {
+		let (_, mut data) = sample_data(Compression::None);
+		// Flips a bit of the block height
+		data[26] ^= 0x01;
+		assert!(matches!(
+			Pixlzr::verify(&data),
+			Err(PixlzrError::ChecksumMismatch { start: 0, .. })
+		));
+	}
+
+	#[test]
+	fn test_decode_lenient() {
+		let (pix, mut data) = sample_data(Compression::Zstd(3));
+		let (start, end) = {
+			let mut reader = super::Reader::new(&data);
+			super::decode_header(&mut reader).unwrap().line_positions[0]
+		};
+		data[(start + end) / 2] ^= 0x01;
+
+		let decoded = Pixlzr::decode_lenient(&data).unwrap();
+		assert_eq!(decoded.blocks.len(), pix.blocks.len());
+		// The first line is flat, of the color of the others, and the
+		// others are intact
+		assert!(decoded.blocks[..3].iter().all(|block| {
+			block.dimensions() == (1, 1)
+				&& block.color() == ColorType::Rgb8
+				&& block.as_slice() == [DAMAGED_LINE_FILL; 3]
+		}));
+		assert!(decoded.blocks[3..]
+			.iter()
+			.zip(pix.blocks[3..].iter())
+			.all(|(a, b)| a.as_slice() == b.as_slice()));
+
+		let img = decoded.to_image(FilterType::Nearest);
+		assert_eq!(img.dimensions(), (40, 40));
+		assert_eq!(img.get_pixel(20, 10).0, [128, 128, 128, 255]);
+		assert_eq!(img.color(), image::ColorType::Rgb8);
+	}
+
+	/// Damages the line of blocks at `row` of the encoded `data`
+	#[allow(dead_code)]
+	fn damage_line(data: &mut [u8], row: usize) {
+		let (start, end) = {
+			let mut reader = super::Reader::new(data);
+			super::decode_header(&mut reader).unwrap().line_positions[row]
+		};
+		data[(start + end) / 2] ^= 0x01;
+	}
+
+	#[test]
+	fn test_decode_lenient_rgba() {
+		let img = RgbaImage::from_fn(40, 40, |x, y| {
+			image::Rgba([(x * 6) as u8, (y * 6) as u8, 90, (x * 5) as u8])
+		});
+		let pix =
+			Pixlzr::from_image(&DynamicImage::ImageRgba8(img), 16, 16);
+		let mut data = pix.encode_to_vec().unwrap();
+		damage_line(&mut data, 1);
+		assert_eq!(Pixlzr::verify(&data).unwrap(), vec![1]);
+
+		let decoded = Pixlzr::decode_lenient(&data).unwrap();
+		assert!(decoded.blocks[3..6].iter().all(|block| {
+			block.color() == ColorType::Rgba8
+				&& block.as_slice() == [128, 128, 128, 255]
+		}));
+		let img = decoded.to_image(FilterType::Nearest);
+		assert_eq!(img.color(), image::ColorType::Rgba8);
+		assert_eq!(img.get_pixel(20, 20).0, [128, 128, 128, 255]);
+	}
+
+	#[test]
+	fn test_decode_lenient_tree() {
+		let img = RgbImage::from_fn(48, 32, |x, y| {
+			image::Rgb([noise(x, y, 251) as u8, (y * 6) as u8, 40])
+		});
+		let pix = Pixlzr::from_image_tree(
+			&DynamicImage::ImageRgb8(img),
+			0.01,
+			(16, 16),
+			(4, 4),
+		)
+		.unwrap();
+		let mut data = pix.encode_to_vec().unwrap();
+		damage_line(&mut data, 0);
+		assert_eq!(Pixlzr::verify(&data).unwrap(), vec![0]);
+
+		// Each cell of the damaged line is a single flat block
+		let decoded = Pixlzr::decode_lenient(&data).unwrap();
+		let rects = decoded.block_rects();
+		assert_eq!(
+			rects[..3],
+			[
+				BlockRect::new(0, 0, 16, 16),
+				BlockRect::new(16, 0, 16, 16),
+				BlockRect::new(32, 0, 16, 16),
+			]
+		);
+		// The second line is intact
+		let intact: Vec<_> = pix
+			.block_rects()
+			.into_iter()
+			.filter(|r| r.y >= 16)
+			.collect();
+		assert_eq!(rects[3..], intact);
+		assert!(decoded.blocks[..3]
+			.iter()
+			.all(|block| block.as_slice() == [DAMAGED_LINE_FILL; 3]));
+		assert_eq!(
+			decoded.to_image(FilterType::Nearest).dimensions(),
+			(48, 32)
+		);
+
+		// And it is still a valid tree, to encode again
+		let again = decoded.encode_to_vec().unwrap();
+		assert_eq!(
+			Pixlzr::decode_from_slice(&again).unwrap().block_rects(),
+			rects
+		);
+	}
+}
@@ -1,242 +1,1291 @@
-#[macro_use]
 pub mod bytes;
+mod chunks;
+pub mod codec;
+mod compression;
+mod integrity;
+mod region;
+mod sequence;
+mod tree;
 
-use self::bytes::Bytes;
+use self::{
+	bytes::{ByteReader, Reader, StreamReader, Writer},
+	chunks::{read_chunks, write_chunks},
+	codec::{
+		decode_compact_payload, decode_payload, encode_payload,
+		scale_code, scale_from_code, BlockCodec, SCALE_CODE_EXPLICIT,
+	},
+	compression::{compress_line, decompress_line},
+	integrity::CrcReader,
+	tree::{decode_node, encode_node},
+};
 use crate::{
 	constants::*,
-	data_types::{Pixlzr, PixlzrBlock, PixlzrBlockRaw, Semver},
+	data_types::{
+		layout::grid_cell, BlockLayout, BlockRect, Compression,
+		FilterType, Metadata, Pixlzr, PixlzrBlock, PixlzrBlockRaw,
+		Preview, Semver,
+	},
+	error::{PixlzrError, Result},
 };
 
+pub use self::sequence::{SequenceReader, SequenceWriter};
+
 use phf;
-use qoi::{self, Result as Result_QOI};
+use std::io::{Read, Write};
 
-#[allow(unused_imports)]
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::iter::{
+	IndexedParallelIterator, IntoParallelIterator, ParallelIterator,
+};
 
 static VERSION_FILTER: phf::Map<&'static str, &'static Semver> = phf::phf_map! {
 	"filter"    => &Semver::new(0, 0, 1),
 	"line-sizes"=> &Semver::new(0, 0, 2),
+	"block-codec"=> &Semver::new(0, 0, 3),
+	"compact"   => &Semver::new(0, 1, 0),
+	"line-compression" => &Semver::new(0, 1, 1),
+	"sample-types" => &Semver::new(0, 1, 2),
+	"metadata" => &Semver::new(0, 1, 3),
+	"checksums" => &Semver::new(0, 1, 4),
+	"sequence" => &Semver::new(0, 1, 5),
+	"tree" => &Semver::new(0, 1, 6),
+	"axis-values" => &Semver::new(0, 1, 7),
 };
 
 fn has_resource(version: &Semver, resource_name: &str) -> bool {
 	VERSION_FILTER
 		.get(resource_name)
-		.map_or(false, |resource_version| version >= *resource_version)
+		.is_some_and(|resource_version| version >= *resource_version)
 }
 
 type Raw = Vec<u8>;
 
-const ERR_ENDED_TOO_SOON: &str = "The slice ended to soon";
+/// Reads `magic.len()` octets, and checks them against `magic`
+fn check_magic<B: ByteReader>(reader: &mut B, magic: &[u8]) -> Result<()> {
+	let offset = reader.position();
+	// All of the magic numbers fit in 8 octets
+	let mut found = [0; 8];
+	let found = &mut found[..magic.len()];
+	reader.read_into(found)?;
+	if found == magic {
+		Ok(())
+	} else {
+		Err(PixlzrError::BadMagic { offset })
+	}
+}
+
+/// The block layout, as written in the header
+const LAYOUT_GRID: u8 = 0;
+const LAYOUT_TREE: u8 = 1;
+
+/// The blocks of a line, each one with the rectangle it covers
+type Line = Vec<(PixlzrBlock, BlockRect)>;
 
 impl Pixlzr {
-	/// Encodes the PIXLZR image into a vector of bytes, following the steps:
-	/// 1. Prepares the image
-	/// 2. Encodes the header
-	/// 3. rayon: Gets each line of blocks
+	/// Encodes the PIXLZR image into a vector of bytes.
+	///
+	/// See `write_to`.
+	pub fn encode_to_vec(&self) -> Result<Raw> {
+		let mut image = Vec::new();
+		self.write_to(&mut image)?;
+		Ok(image)
+	}
+
+	/// Encodes the PIXLZR image into `writer`, following the steps:
+	/// 1. rayon: Gets each line of blocks
 	///    - Encode each block
-	///    - Get the encoded length
-	/// 4. Gets and writes the length of each line
-	/// 5. Appends each block to the final array
-	pub fn encode_to_vec(&self) -> Result_QOI<Raw> {
-		// Gets the numbers of columns and rows
-		let (cols, rows) = (
-			self.block_grid_width() as usize,
-			self.block_grid_height() as usize,
-		);
+	///    - Concatenate the line's blocks
+	///    - Compress the line, keeping it only if smaller
+	/// 2. Encodes the header, and its metadata chunks
+	/// 3. Writes the length and checksum of each line
+	/// 4. Writes the header's checksum
+	/// 5. Writes each line of blocks, one at a time
+	pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+		self.write_frame(writer, self.compression, &[])
+	}
 
-		let mut image =
-			Bytes::new(vec![0; PIXLZR_HEADER_SIZE + (rows * 4)]);
+	/// Encodes the PIXLZR image into `writer`, like `write_to`, but with
+	/// the given `compression`, and writing the blocks flagged in
+	/// `references` as references to the previous frame of a sequence.
+	pub(crate) fn write_frame<W: Write>(
+		&self,
+		mut writer: W,
+		compression: Compression,
+		references: &[bool],
+	) -> Result<()> {
+		let cols = self.block_grid_width() as usize;
+		let rects = self.block_rects();
+		let ranges = self.line_ranges(&rects);
+		if !self.is_grid() {
+			// Every block must be on a line
+			let end = ranges.last().map_or(0, |range| range.end);
+			if end != self.blocks.len() || end != rects.len() {
+				return Err(PixlzrError::LayoutMismatch { index: end });
+			}
+		}
 
-		// Start encoding with the header
-		image.write_slice(PIXLZR_MAGIC_NUMBERS);
-		image.write_slice(PIXLZR_MAGIC_VERSION);
-		image.write_u8(self.filter.unwrap_or_default() as u8);
-		image.write_u32(self.width);
-		image.write_u32(self.height);
-		image.write_u32(self.block_width);
-		image.write_u32(self.block_height);
-
-		let (mut blocks, block_lengths): (Vec<Raw>, Vec<usize>) = {
-			self.par_lines()
-				.flat_map(|line| {
-					// For each line of blocks
-					line.iter()
-						.map(|block| {
-							// For each block
-							// Encode the block, and get the encoded length
-							let (output, len) = encode_block(block);
-							(output.data, len)
+		// Each line, and whether it was compressed
+		let lines: Vec<(Raw, bool)> = ranges
+			.into_par_iter()
+			.enumerate()
+			.map(|(row, range)| {
+				// For each line of blocks
+				let mut output = Writer::new();
+				if self.is_grid() {
+					for (col, index) in range.enumerate() {
+						// Encode the block, and append it to the line
+						let reference = references
+							.get(row * cols + col)
+							.copied()
+							.unwrap_or(false);
+						encode_block(
+							&self.blocks[index],
+							rects[index].dimensions(),
+							reference,
+							&mut output,
+						)?;
+					}
+				} else {
+					// Encode the quadtree of each cell of the line
+					let end = range.end;
+					let mut leaves = range
+						.map(|index| {
+							(index, &self.blocks[index], rects[index])
 						})
-						.collect::<Vec<(Raw, usize)>>()
+						.peekable();
+					for col in 0..cols as u32 {
+						let cell = grid_cell(
+							self.dimensions(),
+							self.block_dimensions(),
+							(col, row as u32),
+						);
+						encode_node(cell, &mut leaves, end, &mut output)?;
+					}
+					if let Some((index, ..)) = leaves.next() {
+						return Err(PixlzrError::LayoutMismatch { index });
+					}
+				}
+				let line = output.into_inner();
+				Ok(match compress_line(&line, compression)? {
+					Some(compressed) => (compressed, true),
+					None => (line, false),
 				})
-				.collect::<Vec<(Raw, usize)>>()
-				.into_iter()
-				.unzip()
-		};
+			})
+			.collect::<Result<_>>()?;
+
+		let mut header =
+			Writer::with_capacity(PIXLZR_HEADER_SIZE + (lines.len() * 4));
 
-		// For each line, write its size
-		(0..rows).for_each(|row| {
-			let idx = row * cols;
-			let sum: usize = block_lengths[idx..(idx + cols)].iter().sum();
-			image.write_u32(sum as u32);
+		// Start encoding with the header
+		header.write_slice(PIXLZR_MAGIC_NUMBERS);
+		header.write_slice(PIXLZR_MAGIC_VERSION);
+		header.write_u8(self.filter.unwrap_or_default() as u8);
+		header.write_u8(compression.as_u8());
+		header.write_u8(if self.is_grid() {
+			LAYOUT_GRID
+		} else {
+			LAYOUT_TREE
 		});
+		header.write_u32(self.width);
+		header.write_u32(self.height);
+		header.write_u32(self.block_width);
+		header.write_u32(self.block_height);
+		let preview = self.preview.then(|| self.render_preview());
+		write_chunks(&self.metadata, preview.as_ref(), &mut header)?;
 
-		// Append the blocks
-		for block in blocks.iter_mut() {
-			image.write_slice(block);
+		// For each line, write its size, flagging the compressed ones,
+		// and its checksum
+		for (line, compressed) in lines.iter() {
+			header.write_varint(
+				(line.len() as u64) << 1 | *compressed as u64,
+			);
+			header.write_u32(crc32fast::hash(line));
 		}
-		Ok(image.data)
+		header.write_u32(crc32fast::hash(header.as_slice()));
+		writer.write_all(header.as_slice())?;
+
+		// Write the blocks, one line at a time
+		for (line, _) in lines {
+			writer.write_all(&line)?;
+		}
+		writer.flush()?;
+		Ok(())
+	}
+
+	/// Decodes the PIXLZR image from a vector of bytes.
+	///
+	/// See `decode_from_slice`.
+	#[inline]
+	pub fn decode_from_vec(inp: Raw) -> Result<Self> {
+		Self::decode_from_slice(&inp)
 	}
 
-	/// Decodes the PIXLZR image from a vector of bytes, following the steps:
+	/// Decodes the PIXLZR image from a slice of bytes, following the steps:
 	/// 1. Extract header
 	/// 2. Get line lengths
-	/// 3.
-	pub fn decode_from_vec(inp: Raw) -> Result_QOI<Self> {
-		let mut reader = Bytes::new(inp);
+	/// 3. rayon: Decode each line of blocks, from views into `inp`, after
+	///    checking and decompressing it
+	///
+	/// Fails with a `PixlzrError` if the data is not a valid PIXLZR image.
+	pub fn decode_from_slice(inp: &[u8]) -> Result<Self> {
+		decode_frame(inp, None)
+	}
 
-		// Get header info
-		assert_eq!(
-			PIXLZR_MAGIC_NUMBERS,
-			reader
-				.read_slice(PIXLZR_MAGIC_NUMBERS.len())
-				.expect(ERR_ENDED_TOO_SOON)
-		);
-		let version: Semver =
-			reader.read_slice(3).expect(ERR_ENDED_TOO_SOON).into();
-		let mut filter = None;
+	/// Decodes the PIXLZR image from `reader`, following the steps:
+	/// 1. Extract header
+	/// 2. Get line lengths
+	/// 3. Read and decode each line of blocks, one at a time
+	///
+	/// Only one line of encoded blocks is kept in memory.
+	/// Fails with a `PixlzrError` if the data is not a valid PIXLZR image.
+	pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+		let mut reader = StreamReader::new(reader);
+		let header = decode_header(&mut reader)?;
 
-		if has_resource(&version, "filter") {
-			filter = Some(reader.read_u8().into());
+		let mut blocks = vec![];
+		for (row, &(start, end)) in
+			header.line_positions.iter().enumerate()
+		{
+			let line = reader.read_vec(end - start)?;
+			let view = Reader::with_offset(&line, start);
+			blocks.extend(header.with_line(row, view, |line| {
+				decode_line(line, &header, row)
+			})?);
 		}
 
-		let width = reader.read_u32();
-		let height = reader.read_u32();
-		let block_width = reader.read_u32();
-		let block_height = reader.read_u32();
+		Ok(header.into_pixlzr(blocks))
+	}
 
-		let cols = (width as f32 / block_width as f32).ceil() as usize;
-		let rows = (height as f32 / block_height as f32).ceil() as usize;
+	/// Reads the preview embedded in the PIXLZR image in `reader`, if any,
+	/// without reading any further than the chunks of the header.
+	///
+	/// See `Pixlzr::preview`.
+	pub fn read_preview_from<R: Read>(
+		reader: R,
+	) -> Result<Option<Preview>> {
+		let mut reader = StreamReader::new(reader);
+		Ok(decode_header_info(&mut reader)?.preview)
+	}
+}
 
-		// Get the length of each line of blocks
-		// In the form `(start, end)[]`
-		let line_positions: Vec<(usize, usize)> = {
-			let line_sizes: Vec<u32> =
-				(0..rows).map(|_| reader.read_u32()).collect();
+/// The decoded header of a PIXLZR image
+pub(crate) struct Header<'a> {
+	pub version: Semver,
+	pub filter: Option<FilterType>,
+	pub compression: Compression,
+	/// Whether each cell of the grid is a quadtree of blocks
+	pub tree: bool,
+	pub width: u32,
+	pub height: u32,
+	pub block_width: u32,
+	pub block_height: u32,
+	pub metadata: Metadata,
+	/// The embedded preview, if any
+	pub preview: Option<Preview>,
+	/// The position of each line of blocks, in the form `(start, end)[]`
+	pub line_positions: Vec<(usize, usize)>,
+	/// Whether each line of blocks is compressed
+	pub compressed_lines: Vec<bool>,
+	/// The CRC32 of each line of blocks, empty before the "checksums"
+	pub line_checksums: Vec<u32>,
+	/// The blocks of the previous frame of a sequence, for the reference
+	/// blocks
+	pub previous: Option<&'a [PixlzrBlock]>,
+}
 
-			let line_positions: Vec<(usize, usize)> = line_sizes
-				.iter()
-				.scan(reader.index(), |sum, x| {
-					let old_sum = *sum;
-					let x = *x as usize;
-					*sum += x;
-					Some((old_sum, *sum))
-				})
-				.collect();
+impl Header<'_> {
+	#[inline]
+	pub fn cols(&self) -> usize {
+		self.width.div_ceil(self.block_width) as usize
+	}
+	#[inline]
+	pub fn rows(&self) -> usize {
+		self.height.div_ceil(self.block_height) as usize
+	}
+	/// The start and end of the line of blocks at `row`
+	pub fn line_position(&self, row: usize) -> Result<(usize, usize)> {
+		self.line_positions.get(row).copied().ok_or_else(|| {
+			PixlzrError::Invalid {
+				// Where the line lengths end
+				offset: self
+					.line_positions
+					.first()
+					.map_or(0, |&(start, _)| start),
+				reason: "the line is missing from the line lengths",
+			}
+		})
+	}
+	/// The rectangle of the cell at (`col`, `row`) of the grid
+	#[inline]
+	pub fn cell(&self, col: usize, row: usize) -> BlockRect {
+		grid_cell(
+			(self.width, self.height),
+			(self.block_width, self.block_height),
+			(col as u32, row as u32),
+		)
+	}
+	/// Calls `f` over the line of blocks at `row`, found in `view`, after
+	/// checking it, and decompressing it if needed
+	pub fn with_line<T>(
+		&self,
+		row: usize,
+		mut view: Reader,
+		f: impl FnOnce(&mut Reader) -> Result<T>,
+	) -> Result<T> {
+		self.check_line(row, &view)?;
+		if !self.compressed_lines[row] {
+			return f(&mut view);
+		}
+		let offset = view.position();
+		let line = decompress_line(
+			view.read_slice(view.remaining())?,
+			self.compression,
+			offset,
+			self.max_line_len(),
+		)?;
+		f(&mut Reader::new(&line))
+	}
+	/// The most octets a line of blocks can take, decompressed
+	pub fn max_line_len(&self) -> usize {
+		let cell = self.block_width as usize * self.block_height as usize;
+		self.cols()
+			.saturating_mul(cell)
+			.saturating_mul(PIXLZR_MAX_PIXEL_OCTETS)
+	}
+	/// Checks the line lengths against the `len` of the available data
+	pub fn check_data_length(&self, len: usize) -> Result<()> {
+		let expected =
+			self.line_positions.last().map_or(len, |&(_, end)| end);
+		if len != expected {
+			return Err(PixlzrError::LineLengthMismatch {
+				expected,
+				found: len,
+			});
+		}
+		Ok(())
+	}
+	pub fn into_pixlzr(self, line: Line) -> Pixlzr {
+		let (blocks, rects) = line.into_iter().unzip();
+		Pixlzr {
+			width: self.width,
+			height: self.height,
+			block_width: self.block_width,
+			block_height: self.block_height,
+			blocks,
+			filter: self.filter,
+			compression: self.compression,
+			metadata: self.metadata,
+			preview: self.preview.is_some(),
+			layout: if self.tree {
+				BlockLayout::Tree(rects)
+			} else {
+				BlockLayout::Grid
+			},
+		}
+	}
+}
 
-			line_positions
-		};
+/// Decodes the header of a PIXLZR image, following the steps:
+/// 1. Extract header info, and read the metadata chunks
+/// 2. Get line lengths and checksums
+/// 3. Check the header's checksum
+pub(crate) fn decode_header<'a, B: ByteReader>(
+	reader: &mut B,
+) -> Result<Header<'a>> {
+	// Hashes the header while reading it
+	let reader = &mut CrcReader::new(reader);
+	let mut header = decode_header_info(reader)?;
+	let version = header.version;
 
-		// Decode and collect the blocks
-		assert_eq!(reader.data.len(), line_positions.last().unwrap().1);
-		let blocks: Vec<_> = line_positions
-			.iter()
-			.flat_map(|&(start, end)| {
-				// Create a view
-				let mut view = bytes_cutout!(reader[start..end]);
-
-				// For each block
-				(0..cols)
-					.map(|_| {
-						PixlzrBlock::from(decode_block(&mut view).unwrap())
-					})
-					.collect::<Vec<_>>()
-			})
-			.collect::<Vec<_>>();
+	// Get the length of each line of blocks
+	let compact = has_resource(&version, "compact");
+	let flagged = has_resource(&version, "line-compression");
+	let checksums = has_resource(&version, "checksums");
+	let mut line_sizes: Vec<usize> = vec![];
+	for _ in 0..header.rows() {
+		line_sizes.push(if compact {
+			reader.read_varint_usize()?
+		} else {
+			reader.read_u32()? as usize
+		});
+		if checksums {
+			header.line_checksums.push(reader.read_u32()?);
+		}
+	}
 
-		Ok(Self {
-			width,
-			height,
-			block_width,
-			block_height,
-			blocks,
-			filter,
+	// The lowest bit of each size flags a compressed line
+	header.compressed_lines = line_sizes
+		.iter_mut()
+		.map(|size| {
+			let compressed = flagged && *size & 1 == 1;
+			if flagged {
+				*size >>= 1;
+			}
+			compressed
 		})
+		.collect();
+
+	// The checksum covers all of the header before it
+	if checksums {
+		let end = reader.position();
+		let expected = reader.checksum();
+		if reader.inner().read_u32()? != expected {
+			return Err(PixlzrError::ChecksumMismatch { start: 0, end });
+		}
 	}
+
+	let mut sum = reader.position();
+	header.line_positions = Vec::with_capacity(line_sizes.len());
+	for size in line_sizes {
+		let start = sum;
+		sum = sum.checked_add(size).ok_or(PixlzrError::Invalid {
+			offset: start,
+			reason: "the line lengths overflow",
+		})?;
+		header.line_positions.push((start, sum));
+	}
+
+	Ok(header)
 }
 
-fn encode_block(block: &PixlzrBlock) -> (Bytes, usize) {
-	let mut output = Bytes::new(vec![0; PIXLZR_BLOCK_HEADER_BASE_SIZE]);
+/// Decodes the header info of a PIXLZR image, and its metadata chunks,
+/// stopping before the line lengths
+fn decode_header_info<'a, B: ByteReader>(
+	reader: &mut B,
+) -> Result<Header<'a>> {
+	check_magic(reader, PIXLZR_MAGIC_NUMBERS)?;
+	let version: Semver = reader.read_array::<3>()?.as_slice().into();
+	if version > PIXLZR_VERSION {
+		return Err(PixlzrError::UnsupportedVersion(version));
+	}
+	let mut filter = None;
+	let mut compression = Compression::None;
+
+	if has_resource(&version, "filter") {
+		filter = Some(reader.read_u8()?.into());
+	}
+	if has_resource(&version, "line-compression") {
+		let offset = reader.position();
+		compression = Compression::from_u8(reader.read_u8()?).ok_or(
+			PixlzrError::Invalid {
+				offset,
+				reason: "unknown compression method",
+			},
+		)?;
+	}
+
+	let mut tree = false;
+	if has_resource(&version, "tree") {
+		let offset = reader.position();
+		tree = match reader.read_u8()? {
+			LAYOUT_GRID => false,
+			LAYOUT_TREE => true,
+			_ => {
+				return Err(PixlzrError::Invalid {
+					offset,
+					reason: "unknown block layout",
+				})
+			}
+		};
+	}
 
-	// Writes PIXLZR_BLOCK magic numbers
-	output.write_slice(PIXLZR_BLOCK_MAGIC_NUMBERS);
-	output.write_f32(if let Some(value) = block.block_value() {
-		value
+	let width = reader.read_u32()?;
+	let height = reader.read_u32()?;
+	let offset = reader.position();
+	let block_width = reader.read_u32()?;
+	let block_height = reader.read_u32()?;
+	if block_width == 0 || block_height == 0 {
+		return Err(PixlzrError::Invalid {
+			offset,
+			reason: "the block dimensions must be positive",
+		});
+	}
+
+	let (metadata, preview) = if has_resource(&version, "metadata") {
+		read_chunks(reader)?
 	} else {
-		// TODO: deal with an absent block value
-		0.
-	});
+		(Metadata::default(), None)
+	};
+
+	Ok(Header {
+		version,
+		filter,
+		compression,
+		tree,
+		width,
+		height,
+		block_width,
+		block_height,
+		metadata,
+		preview,
+		line_positions: vec![],
+		compressed_lines: vec![],
+		line_checksums: vec![],
+		previous: None,
+	})
+}
+
+/// Decodes a PIXLZR image from a slice of bytes, whose reference blocks
+/// point to the `previous` frame's blocks.
+pub(crate) fn decode_frame(
+	inp: &[u8],
+	previous: Option<&[PixlzrBlock]>,
+) -> Result<Pixlzr> {
+	let mut reader = Reader::new(inp);
+	let mut header = decode_header(&mut reader)?;
+	header.check_data_length(reader.len())?;
+	header.previous = previous;
+
+	// Decode and collect the blocks, one line per task
+	let blocks: Vec<_> = (0..header.rows())
+		.into_par_iter()
+		.map(|row| decode_row(&reader, &header, row))
+		.collect::<Result<Vec<_>>>()?
+		.into_iter()
+		.flatten()
+		.collect();
+
+	Ok(header.into_pixlzr(blocks))
+}
+
+/// Checks and decodes the line of blocks at `row`, from its view into
+/// `reader`
+fn decode_row(
+	reader: &Reader,
+	header: &Header,
+	row: usize,
+) -> Result<Line> {
+	let (start, end) = header.line_position(row)?;
+	let view = reader.cutout(start..end)?;
+	header.with_line(row, view, |line| decode_line(line, header, row))
+}
+
+/// Decodes the blocks of the line at `row`, which must fill the whole
+/// `view`
+fn decode_line(
+	view: &mut Reader,
+	header: &Header,
+	row: usize,
+) -> Result<Line> {
+	// Each block takes at least an octet, so the line can't hold more
+	// than its length
+	let mut line = Vec::with_capacity(header.cols().min(view.len()));
+	// For each block, or quadtree of blocks
+	for col in 0..header.cols() {
+		let cell = header.cell(col, row);
+		if header.tree {
+			decode_node(view, header, cell, (col, row), &mut line)?;
+		} else {
+			let block = decode_block(view, header, (col, row))?;
+			line.push((block.into(), cell));
+		}
+	}
 
-	// Create an QOI Encoder
-	let encoder = {
-		let data = block.as_slice();
-		let (width, height) = block.dimensions();
-		qoi::Encoder::new(data, width, height)
+	// The blocks must fill the whole line
+	if view.yet_readding() {
+		return Err(PixlzrError::LineLengthMismatch {
+			expected: view.len(),
+			found: view.index(),
+		});
+	}
+	Ok(line)
+}
+
+/// Encodes the block, in the "compact" layout, appending it to `output`.
+///
+/// `frame` is the block's dimensions in the grid, before being shrunk.
+/// A `reference` block is written without its payload.
+fn encode_block(
+	block: &PixlzrBlock,
+	frame: (u32, u32),
+	reference: bool,
+	output: &mut Writer,
+) -> Result<()> {
+	// A block can't be larger than its frame
+	if block.width() > frame.0 || block.height() > frame.1 {
+		return Err(PixlzrError::Invalid {
+			offset: output.len(),
+			reason: "the block is larger than its cell",
+		});
+	}
+	// Writes the block's dimensions, as a downscale level of its frame
+	match scale_code(frame, block.dimensions()) {
+		Some(code) => output.write_u8(code),
+		None => {
+			output.write_u8(SCALE_CODE_EXPLICIT);
+			output.write_varint(block.width() as u64);
+			output.write_varint(block.height() as u64);
+		}
 	}
-	.unwrap();
+	write_block_value(block, output);
 
-	// Encode in the QOI format
-	let encoded = encoder.encode_to_vec().unwrap();
-	// Discards the QOI magic numbers
-	let encoded = &encoded[QOI_MAGIC_SIZE..];
+	if reference {
+		output.write_u8(
+			BlockCodec::Reference as u8 | (block.color() as u8) << 4,
+		);
+		output.write_varint(0);
+		return Ok(());
+	}
 
-	let len = encoded.len();
-	// Writes the length of the QOI block
-	output.write_u32(len as u32);
-	// Writes the QOI block
-	output.write_slice(encoded);
+	// Encode with the codec that produces the smallest payload
+	let (codec, encoded) = encode_payload(block)?;
 
-	(output, PIXLZR_BLOCK_HEADER_BASE_SIZE + len)
+	// Writes the codec of the block, along with its color type
+	output.write_u8(codec as u8 | (block.color() as u8) << 4);
+	// Writes the length of the payload
+	output.write_varint(encoded.len() as u64);
+	// Writes the payload
+	output.write_slice(&encoded);
+
+	Ok(())
 }
 
-fn decode_block(reader: &mut Bytes) -> Result_QOI<PixlzrBlockRaw> {
+/// Decodes the block at `position` (column, row) in the grid, encoded in
+/// the `header`'s version of the format.
+fn decode_block(
+	reader: &mut Reader,
+	header: &Header,
+	(col, row): (usize, usize),
+) -> Result<PixlzrBlockRaw> {
+	let frame = header.cell(col, row).dimensions();
+	if has_resource(&header.version, "compact") {
+		return decode_compact_block(reader, header, (col, row), frame);
+	}
+
 	// Checks for the header's magic numbers
-	assert_eq!(
-		PIXLZR_BLOCK_MAGIC_NUMBERS,
-		reader
-			.read_slice(PIXLZR_BLOCK_MAGIC_NUMBERS.len())
-			.expect(ERR_ENDED_TOO_SOON)
-	);
+	check_magic(reader, PIXLZR_BLOCK_MAGIC_NUMBERS)?;
 
 	// Get block value
-	let block_value = reader.read_f32();
+	let block_value = reader.read_f32()?;
+	// Get block codec
+	let codec = if has_resource(&header.version, "block-codec") {
+		read_codec(reader)?
+	} else {
+		BlockCodec::Qoi
+	};
 	// Get block length
-	let len = reader.read_u32();
+	let len = reader.read_u32()?;
 
-	// Gets QOI buffer
-	let encoded = {
-		let mut data = QOI_MAGIC.to_vec();
-		data.extend_from_slice(
-			reader.read_slice(len as usize).expect(ERR_ENDED_TOO_SOON),
-		);
-		data
-	};
+	// Decodes the payload
+	let mut payload =
+		reader.cutout(reader.index()..reader.index() + len as usize)?;
+	reader.read_slice(len as usize)?;
+	let (width, height, data) =
+		decode_payload(codec, &mut payload, frame)?;
 
-	// Decodes QOI block
-	let (qoi_header, qoi_data) = qoi::decode_to_vec(encoded)?;
+	Ok(PixlzrBlockRaw {
+		width,
+		height,
+		data,
+		block_value: Some(block_value),
+		axis_values: None,
+	})
+}
 
-	// Gets image data
-	let width = qoi_header.width;
-	let height = qoi_header.height;
-	let data = crate::RawImage {
-		alpha: qoi_header.channels.is_rgba(),
-		data: qoi_data,
-	};
+/// Decodes a block in the "compact" layout, covering a rectangle of the
+/// `frame` dimensions, in the cell at (`col`, `row`) of the grid
+fn decode_compact_block(
+	reader: &mut Reader,
+	header: &Header,
+	(col, row): (usize, usize),
+	frame: (u32, u32),
+) -> Result<PixlzrBlockRaw> {
+	// Get block dimensions
+	let (width, height) = read_dimensions(reader, frame)?;
+	// Get block value
+	let (block_value, axis_values) = read_block_value(reader, header)?;
+	// Get block codec and color type
+	let offset = reader.position();
+	let code = reader.read_u8()?;
+	let codec =
+		BlockCodec::from_u8(code & 0xf).ok_or(PixlzrError::Invalid {
+			offset,
+			reason: "unknown block codec",
+		})?;
+	// Only 8 bits RGB(A) before the "sample-types" resource
+	if code >> 4 > 4 && !has_resource(&header.version, "sample-types") {
+		return Err(PixlzrError::Invalid {
+			offset,
+			reason: "unknown block color type",
+		});
+	}
+	// Get block length
+	let len = reader.read_varint_usize()?;
+
+	// A reference block is the one at its place in the previous frame
+	if codec == BlockCodec::Reference {
+		reader.read_slice(len)?;
+		return header
+			.previous
+			.and_then(|blocks| blocks.get(row * header.cols() + col))
+			.cloned()
+			.map(PixlzrBlockRaw::from)
+			.ok_or(PixlzrError::Invalid {
+				offset,
+				reason: "a reference block needs a previous frame",
+			});
+	}
+
+	// Decodes the payload
+	let offset = reader.position();
+	let data = decode_compact_payload(
+		codec,
+		(width, height),
+		code >> 4,
+		reader.read_slice(len)?,
+		offset,
+	)?;
 
 	Ok(PixlzrBlockRaw {
 		width,
 		height,
 		data,
 		block_value: Some(block_value),
+		axis_values,
 	})
 }
+
+/// Writes the value of a block, in the "compact" layout.
+///
+/// A block shrunk along each axis separately has its horizontal value
+/// written negated, followed by its vertical value. Any other value is
+/// written with its sign bit clear, and a block without a value as a
+/// positive zero.
+fn write_block_value(block: &PixlzrBlock, output: &mut Writer) {
+	match (block.axis_values(), block.block_value()) {
+		(Some((horizontal, vertical)), _) => {
+			output.write_f32(-horizontal.abs());
+			output.write_f32(vertical);
+		}
+		(None, Some(value)) => output.write_f32(value.abs()),
+		(None, None) => output.write_f32(0.),
+	}
+}
+
+/// Reads the value of a block in the "compact" layout, and its axis
+/// values, since the "axis-values" resource
+fn read_block_value(
+	reader: &mut Reader,
+	header: &Header,
+) -> Result<(f32, Option<(f32, f32)>)> {
+	let value = reader.read_f32()?;
+	if !value.is_sign_negative()
+		|| !has_resource(&header.version, "axis-values")
+	{
+		return Ok((value, None));
+	}
+	let axis_values = (-value, reader.read_f32()?);
+	Ok((axis_values.0.hypot(axis_values.1), Some(axis_values)))
+}
+
+/// Reads the dimensions of a block in the "compact" layout, which can't
+/// be larger than its `frame`
+fn read_dimensions(
+	reader: &mut Reader,
+	frame: (u32, u32),
+) -> Result<(u32, u32)> {
+	let offset = reader.position();
+	let code = reader.read_u8()?;
+	if let Some(dimensions) = scale_from_code(frame, code) {
+		return Ok(dimensions);
+	}
+	let width = u32::try_from(reader.read_varint()?);
+	let height = u32::try_from(reader.read_varint()?);
+	match (width, height) {
+		(Ok(width), Ok(height))
+			if width <= frame.0 && height <= frame.1 =>
+		{
+			Ok((width, height))
+		}
+		_ => Err(PixlzrError::Invalid {
+			offset,
+			reason: "the block is larger than its cell",
+		}),
+	}
+}
+
+/// Skips over a block, without decoding its payload
+fn skip_block(reader: &mut Reader, header: &Header) -> Result<()> {
+	if has_resource(&header.version, "compact") {
+		// Any dimensions fit in the largest frame
+		read_dimensions(reader, (u32::MAX, u32::MAX))?;
+		read_block_value(reader, header)?;
+		reader.read_u8()?;
+		let len = reader.read_varint_usize()?;
+		reader.read_slice(len)?;
+		return Ok(());
+	}
+
+	check_magic(reader, PIXLZR_BLOCK_MAGIC_NUMBERS)?;
+	reader.read_f32()?;
+	if has_resource(&header.version, "block-codec") {
+		read_codec(reader)?;
+	}
+	let len = reader.read_u32()?;
+	reader.read_slice(len as usize)?;
+	Ok(())
+}
+
+/// Reads the codec of a block, since the "block-codec" resource
+fn read_codec(reader: &mut Reader) -> Result<BlockCodec> {
+	let offset = reader.position();
+	BlockCodec::from_u8(reader.read_u8()?).ok_or(PixlzrError::Invalid {
+		offset,
+		reason: "unknown block codec",
+	})
+}
+
+pub mod tests_on_encoding {
+	#[allow(unused_imports)]
+	use super::{
+		decode_header, FilterType, Pixlzr, PixlzrError, Reader,
+		PIXLZR_HEADER_SIZE, PIXLZR_MAGIC_NUMBERS,
+	};
+	#[allow(unused_imports)]
+	use crate::data_types::{
+		ColorType, Compression, PixlzrBlockRaw, RawImage,
+	};
+	#[allow(unused_imports)]
+	use crate::policy::{Clamped, PowerOfTwo};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, GrayImage, ImageBuffer, RgbImage};
+
+	#[allow(dead_code)]
+	fn sample_pixlzr() -> Pixlzr {
+		let img = RgbImage::from_fn(40, 24, |x, y| {
+			image::Rgb([
+				(x * 6) as u8,
+				(y * 10) as u8,
+				((x + y) * 3) as u8,
+			])
+		});
+		Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16)
+	}
+
+	/// A single pixel image, of a single block
+	#[allow(dead_code)]
+	fn single_pixel() -> Pixlzr {
+		let block = PixlzrBlockRaw {
+			width: 1,
+			height: 1,
+			block_value: Some(0.),
+			axis_values: None,
+			data: RawImage {
+				color: ColorType::Rgb8,
+				data: vec![10, 20, 30],
+			},
+		};
+		Pixlzr {
+			width: 1,
+			height: 1,
+			block_width: 1,
+			block_height: 1,
+			filter: None,
+			compression: Default::default(),
+			metadata: Default::default(),
+			preview: false,
+			layout: Default::default(),
+			blocks: vec![block.into()],
+		}
+	}
+
+	/// Overwrites the header of `data` with `bytes` at `offset`, and
+	/// writes its checksum again
+	#[allow(dead_code)]
+	fn patch_header(data: &mut [u8], offset: usize, bytes: &[u8]) {
+		let header = decode_header(&mut Reader::new(data)).unwrap();
+		let end = header.line_positions[0].0 - 4;
+		data[offset..offset + bytes.len()].copy_from_slice(bytes);
+		let checksum = crc32fast::hash(&data[..end]);
+		data[end..end + 4].copy_from_slice(&checksum.to_be_bytes());
+	}
+
+	#[test]
+	fn test_encode_decode() {
+		let pix = sample_pixlzr();
+		let data = pix.encode_to_vec().unwrap();
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		assert_eq!(decoded.dimensions(), pix.dimensions());
+		assert_eq!(decoded.block_dimensions(), pix.block_dimensions());
+		assert_eq!(decoded.blocks.len(), pix.blocks.len());
+		assert!(decoded
+			.blocks
+			.iter()
+			.zip(pix.blocks.iter())
+			.all(|(a, b)| a.as_slice() == b.as_slice()));
+	}
+
+	#[test]
+	fn test_encode_decode_gray() {
+		let img = GrayImage::from_fn(40, 24, |x, y| {
+			image::Luma([(x * 3 + y * 5) as u8])
+		});
+		let mut pix = Pixlzr::from_image(
+			&DynamicImage::ImageLuma8(img.clone()),
+			16,
+			16,
+		);
+		let data = pix.encode_to_vec().unwrap();
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		assert!(decoded
+			.blocks
+			.iter()
+			.all(|block| block.color() == ColorType::L8));
+		let image = decoded.to_image(FilterType::Nearest);
+		assert_eq!(image.as_luma8(), Some(&img));
+
+		// Shrinking keeps it gray
+		pix.shrink_by(FilterType::Triangle, 0.5, &PowerOfTwo);
+		let data = pix.encode_to_vec().unwrap();
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		let image = decoded.to_image(FilterType::Triangle);
+		assert!(image.as_luma8().is_some());
+	}
+
+	#[test]
+	fn test_encode_decode_wide() {
+		let rgb16 = ImageBuffer::from_fn(40, 24, |x, y| {
+			image::Rgb([x as u16 * 1500, y as u16 * 2500 + 7, 65535])
+		});
+		let rgba32f = ImageBuffer::from_fn(40, 24, |x, y| {
+			image::Rgba([x as f32 / 7., y as f32 * 10., -0.5, 0.25])
+		});
+		for img in [
+			DynamicImage::ImageRgb16(rgb16),
+			DynamicImage::ImageRgba32F(rgba32f),
+		] {
+			let mut pix = Pixlzr::from_image(&img, 16, 16);
+			pix.compression = Compression::Zstd(3);
+			let data = pix.encode_to_vec().unwrap();
+			let decoded = Pixlzr::decode_from_vec(data).unwrap();
+			let image = decoded.to_image(FilterType::Nearest);
+			assert_eq!(image.color(), img.color());
+			assert_eq!(image.as_bytes(), img.as_bytes());
+
+			// Shrinking keeps the depth
+			pix.shrink_by(FilterType::Triangle, 0.5, &PowerOfTwo);
+			let data = pix.encode_to_vec().unwrap();
+			let decoded = Pixlzr::decode_from_vec(data).unwrap();
+			let image = decoded.to_image(FilterType::Triangle);
+			assert_eq!(image.color(), img.color());
+		}
+	}
+
+	#[test]
+	fn test_encode_decode_compressed() {
+		let mut pix = sample_pixlzr();
+		let plain = pix.encode_to_vec().unwrap();
+		for compression in [Compression::Deflate(9), Compression::Zstd(19)]
+		{
+			pix.compression = compression;
+			let data = pix.encode_to_vec().unwrap();
+			assert!(data.len() < plain.len());
+
+			let decoded = Pixlzr::decode_from_slice(&data).unwrap();
+			assert_eq!(decoded.compression.as_u8(), compression.as_u8());
+			assert!(decoded
+				.blocks
+				.iter()
+				.zip(pix.blocks.iter())
+				.all(|(a, b)| a.as_slice() == b.as_slice()));
+
+			let streamed = Pixlzr::read_from(data.as_slice()).unwrap();
+			assert_eq!(streamed.blocks.len(), pix.blocks.len());
+			let region =
+				Pixlzr::decode_region(&data, 20, 0, 8, 8).unwrap();
+			assert_eq!(
+				region.blocks[0].as_slice(),
+				pix.blocks[1].as_slice()
+			);
+		}
+	}
+
+	#[test]
+	fn test_encode_decode_metadata() {
+		let mut pix = sample_pixlzr();
+		pix.compression = Compression::Zstd(3);
+		pix.metadata.icc = Some(vec![7; 300]);
+		pix.metadata.xmp = Some(b"<x:xmpmeta/>".to_vec());
+		pix.metadata.push_text("Title", "Sample");
+		let data = pix.encode_to_vec().unwrap();
+
+		let decoded = Pixlzr::decode_from_slice(&data).unwrap();
+		assert_eq!(decoded.metadata, pix.metadata);
+		let streamed = Pixlzr::read_from(data.as_slice()).unwrap();
+		assert_eq!(streamed.metadata, pix.metadata);
+		assert_eq!(
+			streamed.blocks[4].as_slice(),
+			pix.blocks[4].as_slice()
+		);
+	}
+
+	#[test]
+	fn test_encode_preview() {
+		let mut pix = sample_pixlzr();
+		let data = pix.encode_to_vec().unwrap();
+		assert_eq!(
+			Pixlzr::read_preview_from(data.as_slice()).unwrap(),
+			None
+		);
+
+		pix.preview = true;
+		let data = pix.encode_to_vec().unwrap();
+		// Only the data up to the end of the preview chunk is needed: the
+		// chunk count, tag, length, and a QOI image of the 3x2 averages
+		let rendered = pix.render_preview();
+		let qoi_len =
+			qoi::encode_to_vec(&rendered.data, 3, 2).unwrap().len();
+		let end = PIXLZR_HEADER_SIZE + 1 + 4 + 1 + qoi_len;
+		let preview =
+			Pixlzr::read_preview_from(&data[..end]).unwrap().unwrap();
+		assert_eq!(preview.dimensions(), (3, 2));
+		assert_eq!(preview, rendered);
+		// The first block averages x in 0..16 and y in 0..16
+		assert_eq!(&preview.data[..4], [45, 75, 45, 255]);
+
+		let decoded = Pixlzr::decode_from_slice(&data).unwrap();
+		assert!(decoded.preview);
+		assert_eq!(decoded.blocks[5].as_slice(), pix.blocks[5].as_slice());
+	}
+
+	#[test]
+	fn test_decode_parallel_matches_sequential() {
+		let data = sample_pixlzr().encode_to_vec().unwrap();
+		let parallel = Pixlzr::decode_from_slice(&data).unwrap();
+		let sequential = rayon::ThreadPoolBuilder::new()
+			.num_threads(1)
+			.build()
+			.unwrap()
+			.install(|| Pixlzr::decode_from_slice(&data))
+			.unwrap();
+		assert_eq!(parallel.blocks.len(), sequential.blocks.len());
+		assert!(parallel.blocks.iter().zip(sequential.blocks.iter()).all(
+			|(a, b)| a.dimensions() == b.dimensions()
+				&& a.block_value() == b.block_value()
+				&& a.as_slice() == b.as_slice()
+		));
+	}
+
+	#[test]
+	fn test_write_read_stream() {
+		let pix = sample_pixlzr();
+		let mut stream = Vec::new();
+		pix.write_to(&mut stream).unwrap();
+		assert_eq!(stream, pix.encode_to_vec().unwrap());
+
+		let decoded = Pixlzr::read_from(stream.as_slice()).unwrap();
+		assert_eq!(decoded.dimensions(), pix.dimensions());
+		assert!(decoded
+			.blocks
+			.iter()
+			.zip(pix.blocks.iter())
+			.all(|(a, b)| a.as_slice() == b.as_slice()));
+
+		let res = Pixlzr::read_from(&stream[..stream.len() - 1]);
+		assert!(matches!(res, Err(PixlzrError::Truncated { .. })));
+	}
+
+	#[test]
+	fn test_decode_version_0_0_2() {
+		// Encoded before the "block-codec" resource
+		let data = std::fs::read("benches/base.pixlzr").unwrap();
+		assert_eq!(&data[6..9], &[0, 0, 2]);
+		let pix = Pixlzr::decode_from_slice(&data).unwrap();
+		assert_eq!(pix.dimensions(), (1080, 1617));
+		assert_eq!(pix.blocks.len(), 17 * 26);
+	}
+
+	#[test]
+	fn test_decode_truncated() {
+		let data = sample_pixlzr().encode_to_vec().unwrap();
+		for len in 0..data.len() {
+			let res = Pixlzr::decode_from_vec(data[..len].to_vec());
+			assert!(res.is_err(), "Decoded with only {len} octets");
+		}
+		let res = Pixlzr::decode_from_vec(data[..10].to_vec());
+		assert!(matches!(res, Err(PixlzrError::Truncated { offset: 10 })));
+	}
+
+	#[test]
+	fn test_decode_huge_width() {
+		// The width of 2^32 - 1 blocks must not be allocated up front
+		let mut data = single_pixel().encode_to_vec().unwrap();
+		let width = PIXLZR_MAGIC_NUMBERS.len() + 3 + 3;
+		patch_header(&mut data, width, &u32::MAX.to_be_bytes());
+		assert!(Pixlzr::decode_from_vec(data).is_err());
+	}
+
+	#[test]
+	fn test_decode_bad_magic() {
+		let mut data = sample_pixlzr().encode_to_vec().unwrap();
+		data[0] = b'X';
+		let res = Pixlzr::decode_from_vec(data);
+		assert!(matches!(res, Err(PixlzrError::BadMagic { offset: 0 })));
+	}
+
+	#[test]
+	fn test_decode_unsupported_version() {
+		let mut data = sample_pixlzr().encode_to_vec().unwrap();
+		data[PIXLZR_MAGIC_NUMBERS.len()] = 255;
+		let res = Pixlzr::decode_from_vec(data);
+		assert!(matches!(res, Err(PixlzrError::UnsupportedVersion(_))));
+	}
+
+	#[test]
+	fn test_decode_line_length_mismatch() {
+		let mut data = sample_pixlzr().encode_to_vec().unwrap();
+		data.push(0);
+		let res = Pixlzr::decode_from_vec(data);
+		assert!(matches!(
+			res,
+			Err(PixlzrError::LineLengthMismatch { .. })
+		));
+	}
+
+	#[test]
+	fn test_encode_compact() {
+		// 2 solid blocks, shrunk to a single pixel each
+		let block = |color: u8| {
+			PixlzrBlockRaw {
+				width: 1,
+				height: 1,
+				block_value: Some(0.),
+				axis_values: None,
+				data: RawImage {
+					color: ColorType::Rgb8,
+					data: vec![color; 3],
+				},
+			}
+			.into()
+		};
+		let pix = Pixlzr {
+			width: 16,
+			height: 8,
+			block_width: 8,
+			block_height: 8,
+			filter: None,
+			compression: Default::default(),
+			metadata: Default::default(),
+			preview: false,
+			layout: Default::default(),
+			blocks: vec![block(10), block(20)],
+		};
+		let data = pix.encode_to_vec().unwrap();
+		// No chunks, a line size and checksum, the header's checksum, and
+		// per block: scale code, value, codec, payload length and a pixel
+		assert_eq!(
+			data.len(),
+			PIXLZR_HEADER_SIZE + 1 + 1 + 4 + 4 + 2 * (1 + 4 + 1 + 1 + 3)
+		);
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		assert_eq!(decoded.blocks[1].dimensions(), (1, 1));
+		assert_eq!(decoded.blocks[1].as_slice(), &[20; 3]);
+	}
+
+	#[test]
+	fn test_encode_explicit_dimensions() {
+		// Blocks that aren't a power-of-two downscale of their frame
+		let mut pix = sample_pixlzr();
+		let block = PixlzrBlockRaw {
+			width: 5,
+			height: 3,
+			block_value: Some(1.),
+			axis_values: None,
+			data: RawImage {
+				color: ColorType::Rgb8,
+				data: (0..5 * 3 * 3).collect(),
+			},
+		};
+		pix.blocks[4] = block.into();
+		let data = pix.encode_to_vec().unwrap();
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		assert_eq!(decoded.blocks[4].dimensions(), (5, 3));
+		assert_eq!(decoded.blocks[4].as_slice(), pix.blocks[4].as_slice());
+	}
+
+	#[test]
+	fn test_decode_block_larger_than_cell() {
+		// A solid 3x1 block, with explicit dimensions, in a 4x1 cell
+		let mut pix = single_pixel();
+		pix.width = 4;
+		pix.block_width = 4;
+		pix.blocks[0] = PixlzrBlockRaw {
+			width: 3,
+			height: 1,
+			block_value: Some(0.),
+			axis_values: None,
+			data: RawImage {
+				color: ColorType::Rgb8,
+				data: vec![7; 3 * 3],
+			},
+		}
+		.into();
+		let mut data = pix.encode_to_vec().unwrap();
+		assert!(Pixlzr::decode_from_slice(&data).is_ok());
+
+		// A single pixel image shrinks the cell below the block
+		let width = PIXLZR_MAGIC_NUMBERS.len() + 3 + 3;
+		patch_header(&mut data, width, &1u32.to_be_bytes());
+		let res = Pixlzr::decode_from_vec(data);
+		assert!(matches!(res, Err(PixlzrError::Invalid { .. })));
+
+		// Nor can such a block be encoded
+		pix.width = 2;
+		pix.block_width = 2;
+		assert!(pix.encode_to_vec().is_err());
+	}
+
+	#[test]
+	fn test_encode_axis_values_reshrunk() {
+		// Blocks kept whole, shrunk along each axis, then as a whole
+		let img = RgbImage::from_fn(40, 24, |x, _| {
+			image::Rgb([(x % 2 * 200) as u8, (x * 6) as u8, 90])
+		});
+		let mut pix =
+			Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16);
+		let whole = Clamped::new(PowerOfTwo, 1., 1.);
+		pix.shrink_directionally(FilterType::Nearest, 1., &whole);
+		assert!(pix.blocks.iter().all(|b| b.axis_values().is_some()));
+		pix.shrink_directionally(FilterType::Nearest, 0., &whole);
+		assert!(pix
+			.blocks
+			.iter()
+			.all(|b| b.axis_values().is_none()
+				&& b.block_value() == Some(0.)));
+
+		let data = pix.encode_to_vec().unwrap();
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		assert!(decoded
+			.blocks
+			.iter()
+			.all(|b| b.axis_values().is_none()
+				&& b.block_value() == Some(0.)));
+	}
+
+	#[test]
+	fn test_encode_axis_values() {
+		// Vertical stripes: detailed across, flat along
+		let img = RgbImage::from_fn(40, 24, |x, _| {
+			image::Rgb([(x % 2 * 200) as u8, (x * 6) as u8, 90])
+		});
+		let mut pix =
+			Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16);
+		pix.shrink_directionally(FilterType::Nearest, 64., &PowerOfTwo);
+		assert!(pix.blocks.iter().all(|block| block
+			.axis_values()
+			.is_some_and(|(hz, vr)| hz != vr)));
+		// Shrunk down to a single column, keeping every line
+		assert!(pix.block_scale_levels().iter().all(
+			|levels| levels.is_some_and(|(hz, vr)| hz > 0 && vr == 0)
+		));
+
+		let data = pix.encode_to_vec().unwrap();
+		let decoded = Pixlzr::decode_from_vec(data).unwrap();
+		assert!(decoded
+			.blocks
+			.iter()
+			.zip(pix.blocks.iter())
+			.all(|(a, b)| a.axis_values() == b.axis_values()
+				&& a.block_value() == b.block_value()));
+		assert_eq!(decoded.block_scale_levels(), pix.block_scale_levels());
+	}
+}
@@ -0,0 +1,244 @@
+use super::{
+	bytes::Reader, decode_block, decode_header, decode_line, skip_block,
+	Line,
+};
+use crate::{
+	data_types::{BlockLayout, BlockRect, Pixlzr, PixlzrBlock},
+	error::{PixlzrError, Result},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+#[cfg(feature = "image-rs")]
+use crate::data_types::FilterType;
+#[cfg(feature = "image-rs")]
+use image::DynamicImage;
+
+impl Pixlzr {
+	/// Decodes only the blocks of a PIXLZR image that intersect the
+	/// rectangle of size `width` x `height` at (`x`, `y`).
+	///
+	/// The lines of blocks that fall outside the rectangle are skipped
+	/// through the LINE_LENGTHS table, and the blocks outside of it on the
+	/// remaining lines are skipped without being decoded (but compressed
+	/// lines are still decompressed whole).
+	///
+	/// As the result is made of whole blocks, it covers the rectangle
+	/// aligned to the block grid: its top-left corner is at
+	/// `(x - x % block_width, y - y % block_height)` of the original image.
+	/// For an image of exactly the rectangle, see `decode_region_image`.
+	///
+	/// Fails with `PixlzrError::RegionOutOfBounds` if the rectangle doesn't
+	/// intersect the image.
+	pub fn decode_region(
+		inp: &[u8],
+		x: u32,
+		y: u32,
+		width: u32,
+		height: u32,
+	) -> Result<Self> {
+		let mut reader = Reader::new(inp);
+		let header = decode_header(&mut reader)?;
+		header.check_data_length(reader.len())?;
+
+		// Clamps the rectangle to the image
+		let x1 = x.saturating_add(width).min(header.width);
+		let y1 = y.saturating_add(height).min(header.height);
+		if x >= x1 || y >= y1 {
+			return Err(PixlzrError::RegionOutOfBounds {
+				x,
+				y,
+				width,
+				height,
+			});
+		}
+
+		// The blocks' columns and rows that intersect the rectangle
+		let (block_width, block_height) =
+			(header.block_width, header.block_height);
+		let (col0, col1) = (
+			(x / block_width) as usize,
+			x1.div_ceil(block_width) as usize,
+		);
+		let (row0, row1) = (
+			(y / block_height) as usize,
+			y1.div_ceil(block_height) as usize,
+		);
+
+		// Decode and collect the blocks, one line per task
+		let lines = (row0..row1)
+			.into_par_iter()
+			.map(|row| {
+				let (start, end) = header.line_position(row)?;
+				let view = reader.cutout(start..end)?;
+
+				header.with_line(row, view, |view| {
+					// The quadtrees can't be skipped, so their whole line
+					// is decoded
+					if header.tree {
+						let line = decode_line(view, &header, row)?;
+						return Ok(line
+							.into_iter()
+							.filter(|(_, rect)| {
+								(col0..col1).contains(
+									&((rect.x / block_width) as usize),
+								)
+							})
+							.collect());
+					}
+					// Skips the blocks before the rectangle
+					for _ in 0..col0 {
+						skip_block(view, &header)?;
+					}
+					// And decodes the ones inside it
+					(col0..col1)
+						.map(|col| {
+							decode_block(view, &header, (col, row)).map(
+								|block| {
+									(block.into(), header.cell(col, row))
+								},
+							)
+						})
+						.collect::<Result<Line>>()
+				})
+			})
+			.collect::<Result<Vec<_>>>()?;
+
+		// The rectangles are moved along with the region
+		let (x0, y0) =
+			(col0 as u32 * block_width, row0 as u32 * block_height);
+		let (blocks, rects): (Vec<PixlzrBlock>, Vec<_>) = lines
+			.into_iter()
+			.flatten()
+			.map(|(block, rect)| {
+				let rect = BlockRect {
+					x: rect.x - x0,
+					y: rect.y - y0,
+					..rect
+				};
+				(block, rect)
+			})
+			.unzip();
+		Ok(Self {
+			width: (col1 as u32 * block_width).min(header.width) - x0,
+			height: (row1 as u32 * block_height).min(header.height) - y0,
+			block_width,
+			block_height,
+			filter: header.filter,
+			compression: header.compression,
+			metadata: header.metadata,
+			preview: header.preview.is_some(),
+			layout: if header.tree {
+				BlockLayout::Tree(rects)
+			} else {
+				BlockLayout::Grid
+			},
+			blocks,
+		})
+	}
+
+	#[cfg(feature = "image-rs")]
+	/// Decodes only the blocks of a PIXLZR image that intersect the
+	/// rectangle of size `width` x `height` at (`x`, `y`), and returns an
+	/// image of exactly that rectangle, clamped to the image's bounds.
+	///
+	/// See `decode_region`.
+	pub fn decode_region_image(
+		inp: &[u8],
+		x: u32,
+		y: u32,
+		width: u32,
+		height: u32,
+		filter: FilterType,
+	) -> Result<DynamicImage> {
+		let pix = Self::decode_region(inp, x, y, width, height)?;
+		// Offset of the rectangle inside the decoded blocks
+		let (dx, dy) = (x % pix.block_width, y % pix.block_height);
+		let image = pix.to_image(filter);
+		Ok(image.crop_imm(
+			dx,
+			dy,
+			width.min(pix.width - dx),
+			height.min(pix.height - dy),
+		))
+	}
+}
+
+pub mod tests_on_region {
+	#[allow(unused_imports)]
+	use super::{Pixlzr, PixlzrError};
+	#[allow(unused_imports)]
+	use crate::data_types::FilterType;
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use image::{DynamicImage, GenericImageView, RgbImage};
+
+	#[allow(dead_code)]
+	fn sample_data() -> Vec<u8> {
+		let img = RgbImage::from_fn(70, 50, |x, y| {
+			image::Rgb([(x * 3) as u8, (y * 5) as u8, ((x ^ y) * 4) as u8])
+		});
+		let mut pix =
+			Pixlzr::from_image(&DynamicImage::ImageRgb8(img), 16, 16);
+		pix.shrink_by(FilterType::Nearest, 0.5, &PowerOfTwo);
+		pix.encode_to_vec().unwrap()
+	}
+
+	#[test]
+	fn test_decode_region() {
+		let data = sample_data();
+		let full = Pixlzr::decode_from_slice(&data).unwrap();
+		let region = Pixlzr::decode_region(&data, 20, 10, 30, 40).unwrap();
+
+		// Columns 1..4 and rows 0..4, with the last row trailing
+		assert_eq!(region.dimensions(), (48, 50));
+		assert_eq!(region.block_grid_dimensions(), (3, 4));
+		// Exactly, even beyond the integers of an f32
+		let mut tall = region.clone();
+		(tall.height, tall.block_height) = ((1 << 25) + 1, 2);
+		assert_eq!(tall.block_grid_height(), (1 << 24) + 1);
+		let cols = full.block_grid_width() as usize;
+		for (i, block) in region.blocks.iter().enumerate() {
+			let (col, row) = (1 + i % 3, i / 3);
+			let orig = &full.blocks[row * cols + col];
+			assert_eq!(block.dimensions(), orig.dimensions());
+			assert_eq!(block.as_slice(), orig.as_slice());
+		}
+	}
+
+	#[test]
+	fn test_decode_region_image() {
+		let data = sample_data();
+		let full = Pixlzr::decode_from_slice(&data)
+			.unwrap()
+			.to_image(FilterType::Nearest);
+		let region = Pixlzr::decode_region_image(
+			&data,
+			20,
+			10,
+			30,
+			100,
+			FilterType::Nearest,
+		)
+		.unwrap();
+		assert_eq!(region.dimensions(), (30, 40));
+		assert_eq!(
+			region.as_bytes(),
+			full.crop_imm(20, 10, 30, 40).as_bytes()
+		);
+	}
+
+	#[test]
+	fn test_decode_region_out_of_bounds() {
+		let data = sample_data();
+		assert!(matches!(
+			Pixlzr::decode_region(&data, 70, 0, 10, 10),
+			Err(PixlzrError::RegionOutOfBounds { .. })
+		));
+		assert!(matches!(
+			Pixlzr::decode_region(&data, 0, 0, 10, 0),
+			Err(PixlzrError::RegionOutOfBounds { .. })
+		));
+	}
+}
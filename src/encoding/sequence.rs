@@ -0,0 +1,463 @@
+use super::{
+	bytes::{ByteReader, StreamReader, Writer},
+	check_magic,
+	chunks::{read_chunks, write_chunks},
+	decode_frame, has_resource,
+	integrity::CrcReader,
+	Raw,
+};
+use crate::{
+	constants::*,
+	data_types::{
+		Compression, Metadata, Pixlzr, PixlzrBlock, PixlzrFrame,
+		PixlzrSequence, Semver, DEFAULT_SEQUENCE_THRESHOLD,
+	},
+	error::{PixlzrError, Result},
+	operations::get_block_difference,
+};
+
+use rayon::iter::{
+	IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use std::io::{Read, Write};
+
+impl PixlzrSequence {
+	/// Encodes the sequence into a vector of bytes.
+	///
+	/// See `write_to`.
+	pub fn encode_to_vec(&self) -> Result<Raw> {
+		let mut sequence = Vec::new();
+		self.write_to(&mut sequence)?;
+		Ok(sequence)
+	}
+
+	/// Encodes the sequence into `writer`, through a `SequenceWriter`.
+	///
+	/// Fails with `PixlzrError::FrameMismatch` if a frame doesn't have the
+	/// sequence's block grid.
+	pub fn write_to<W: Write>(&self, writer: W) -> Result<()> {
+		let mut frames = SequenceWriter::new(
+			writer,
+			self.dimensions(),
+			self.block_dimensions(),
+			&self.metadata,
+		)?;
+		frames.threshold = self.threshold;
+		frames.compression = self.compression;
+		for frame in self.frames.iter() {
+			frames.write_frame(&frame.image, frame.delay)?;
+		}
+		frames.finish()?;
+		Ok(())
+	}
+
+	/// Decodes the sequence from a slice of bytes.
+	///
+	/// See `read_from`.
+	#[inline]
+	pub fn decode_from_slice(inp: &[u8]) -> Result<Self> {
+		Self::read_from(inp)
+	}
+
+	/// Decodes the whole sequence from `reader`.
+	///
+	/// The `compression` is the first frame's, and the `threshold` is the
+	/// default one, as neither is stored. To decode one frame at a time,
+	/// see `SequenceReader`.
+	pub fn read_from<R: Read>(reader: R) -> Result<Self> {
+		let mut frames = SequenceReader::new(reader)?;
+		let (width, height) = frames.dimensions();
+		let (block_width, block_height) = frames.block_dimensions();
+		let mut sequence =
+			Self::new(width, height, block_width, block_height);
+		sequence.metadata = std::mem::take(&mut frames.metadata);
+		for frame in frames {
+			sequence.frames.push(frame?);
+		}
+		if let Some(frame) = sequence.frames.first() {
+			sequence.compression = frame.image.compression;
+		}
+		Ok(sequence)
+	}
+}
+
+/// Encodes a `PixlzrSequence` into a writer, one frame at a time.
+///
+/// Each block that differs by at most `threshold` from the one the
+/// decoder will have at its place, after the previous frame, is written as
+/// a reference to it.
+/// Only the blocks of the previous frame are kept in memory.
+pub struct SequenceWriter<W: Write> {
+	writer: W,
+	width: u32,
+	height: u32,
+	block_width: u32,
+	block_height: u32,
+	/// The biggest mean difference, in `[0; 1]`, of a block to the one at
+	/// its place in the previous frame, for it to be written as a reference
+	pub threshold: f32,
+	/// The second stage compression of each frame
+	pub compression: Compression,
+	/// The index of the next frame
+	index: usize,
+	/// The blocks the decoder will have, after the previous frame
+	previous: Vec<PixlzrBlock>,
+}
+
+impl<W: Write> SequenceWriter<W> {
+	/// Writes the header of a sequence of frames of `dimensions`, split in
+	/// blocks of `block_dimensions`, to `writer`.
+	pub fn new(
+		mut writer: W,
+		(width, height): (u32, u32),
+		(block_width, block_height): (u32, u32),
+		metadata: &Metadata,
+	) -> Result<Self> {
+		let mut header = Writer::with_capacity(PIXLZR_HEADER_SIZE + 4);
+		header.write_slice(PIXLZR_SEQUENCE_MAGIC_NUMBERS);
+		header.write_slice(PIXLZR_MAGIC_VERSION);
+		header.write_u32(width);
+		header.write_u32(height);
+		header.write_u32(block_width);
+		header.write_u32(block_height);
+		write_chunks(metadata, None, &mut header)?;
+		header.write_u32(crc32fast::hash(header.as_slice()));
+		writer.write_all(header.as_slice())?;
+
+		Ok(Self {
+			writer,
+			width,
+			height,
+			block_width,
+			block_height,
+			threshold: DEFAULT_SEQUENCE_THRESHOLD,
+			compression: Compression::None,
+			index: 0,
+			previous: vec![],
+		})
+	}
+
+	/// Encodes and writes a frame, shown for `delay` milliseconds, as a
+	/// ratio of (numerator, denominator), following the steps:
+	/// 1. rayon: Flags the blocks close enough to the previous frame's
+	/// 2. Encodes the frame as a PIXLZR image, with the flagged blocks as
+	///    references
+	/// 3. Writes its length, its delay, and the encoded frame
+	///
+	/// Fails with `PixlzrError::FrameMismatch` if the `image` doesn't have
+	/// the sequence's dimensions and block dimensions.
+	pub fn write_frame(
+		&mut self,
+		image: &Pixlzr,
+		delay: (u32, u32),
+	) -> Result<()> {
+		if !image.is_grid()
+			|| image.dimensions() != (self.width, self.height)
+			|| image.block_dimensions()
+				!= (self.block_width, self.block_height)
+			|| image.blocks.len()
+				!= (image.block_grid_width() * image.block_grid_height())
+					as usize
+		{
+			return Err(PixlzrError::FrameMismatch { index: self.index });
+		}
+
+		let references: Vec<bool> = self
+			.previous
+			.par_iter()
+			.zip(image.blocks.par_iter())
+			.map(|(previous, block)| {
+				get_block_difference(previous, block)
+					.is_some_and(|difference| difference <= self.threshold)
+			})
+			.collect();
+
+		let mut data = Vec::new();
+		image.write_frame(&mut data, self.compression, &references)?;
+
+		// The unflagged blocks replace the previous ones
+		if self.previous.is_empty() {
+			self.previous = image.blocks.clone();
+		} else {
+			for ((previous, block), _) in self
+				.previous
+				.iter_mut()
+				.zip(image.blocks.iter())
+				.zip(references.iter())
+				.filter(|(_, &reference)| !reference)
+			{
+				*previous = block.clone();
+			}
+		}
+
+		let mut head = Writer::with_capacity(16);
+		head.write_varint(data.len() as u64);
+		head.write_varint(delay.0 as u64);
+		head.write_varint(delay.1 as u64);
+		self.writer.write_all(head.as_slice())?;
+		self.writer.write_all(&data)?;
+		self.index += 1;
+		Ok(())
+	}
+
+	/// Ends the sequence, returning the underlying writer.
+	pub fn finish(mut self) -> Result<W> {
+		// An empty frame ends the sequence
+		self.writer.write_all(&[0])?;
+		self.writer.flush()?;
+		Ok(self.writer)
+	}
+}
+
+/// An iterator over the frames of an encoded `PixlzrSequence`, that reads
+/// and decodes one frame at a time.
+///
+/// Only the current and the previous frames are kept in memory.
+/// The offsets of the errors inside a frame are relative to its start.
+pub struct SequenceReader<R: Read> {
+	reader: StreamReader<R>,
+	width: u32,
+	height: u32,
+	block_width: u32,
+	block_height: u32,
+	metadata: Metadata,
+	/// The index of the next frame
+	index: usize,
+	/// Whether the end of the sequence, or an error, was reached
+	done: bool,
+	previous: Option<Pixlzr>,
+}
+
+impl<R: Read> SequenceReader<R> {
+	/// Reads the header of the sequence from `reader`.
+	///
+	/// Fails with a `PixlzrError` if the data is not a valid PIXLZR
+	/// sequence.
+	pub fn new(reader: R) -> Result<Self> {
+		let mut stream = StreamReader::new(reader);
+		// Hashes the header while reading it
+		let reader = &mut CrcReader::new(&mut stream);
+
+		check_magic(reader, PIXLZR_SEQUENCE_MAGIC_NUMBERS)?;
+		let version: Semver = reader.read_array::<3>()?.as_slice().into();
+		if version > PIXLZR_VERSION || !has_resource(&version, "sequence")
+		{
+			return Err(PixlzrError::UnsupportedVersion(version));
+		}
+		let width = reader.read_u32()?;
+		let height = reader.read_u32()?;
+		let offset = reader.position();
+		let block_width = reader.read_u32()?;
+		let block_height = reader.read_u32()?;
+		if block_width == 0 || block_height == 0 {
+			return Err(PixlzrError::Invalid {
+				offset,
+				reason: "the block dimensions must be positive",
+			});
+		}
+		let (metadata, _) = read_chunks(reader)?;
+
+		// The checksum covers all of the header before it
+		let end = reader.position();
+		let expected = reader.checksum();
+		if reader.inner().read_u32()? != expected {
+			return Err(PixlzrError::ChecksumMismatch { start: 0, end });
+		}
+
+		Ok(Self {
+			reader: stream,
+			width,
+			height,
+			block_width,
+			block_height,
+			metadata,
+			index: 0,
+			done: false,
+			previous: None,
+		})
+	}
+
+	/// Returns the width and height of the frames as a tuple of u32.
+	pub fn dimensions(&self) -> (u32, u32) {
+		(self.width, self.height)
+	}
+	/// Returns the common block dimensions in the frames as a tuple of
+	/// (width, height).
+	pub fn block_dimensions(&self) -> (u32, u32) {
+		(self.block_width, self.block_height)
+	}
+	/// The metadata of the whole sequence.
+	pub fn metadata(&self) -> &Metadata {
+		&self.metadata
+	}
+
+	/// Reads and decodes the next frame, or `None` at the end of the
+	/// sequence
+	fn read_frame(&mut self) -> Result<Option<PixlzrFrame>> {
+		let reader = &mut self.reader;
+		let len = reader.read_varint_usize()?;
+		if len == 0 {
+			return Ok(None);
+		}
+		let offset = reader.position();
+		let (numerator, denominator) = (
+			u32::try_from(reader.read_varint()?),
+			u32::try_from(reader.read_varint()?),
+		);
+		let (Ok(numerator), Ok(denominator)) = (numerator, denominator)
+		else {
+			return Err(PixlzrError::Invalid {
+				offset,
+				reason: "the frame delay overflows u32",
+			});
+		};
+		let data = reader.read_vec(len)?;
+
+		let previous = self.previous.as_ref().map(|p| p.blocks.as_slice());
+		let image = decode_frame(&data, previous)?;
+		if image.dimensions() != self.dimensions()
+			|| image.block_dimensions() != self.block_dimensions()
+		{
+			return Err(PixlzrError::FrameMismatch { index: self.index });
+		}
+		self.previous = Some(image.clone());
+		self.index += 1;
+		Ok(Some(PixlzrFrame {
+			image,
+			delay: (numerator, denominator),
+		}))
+	}
+}
+
+impl<R: Read> Iterator for SequenceReader<R> {
+	type Item = Result<PixlzrFrame>;
+
+	/// Reads and decodes the next frame, stopping after the first error
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done {
+			return None;
+		}
+		let frame = self.read_frame().transpose();
+		self.done = !matches!(frame, Some(Ok(_)));
+		frame
+	}
+}
+
+pub mod tests_on_sequence {
+	#[allow(unused_imports)]
+	use super::{PixlzrSequence, SequenceReader, SequenceWriter};
+	#[allow(unused_imports)]
+	use crate::{data_types::Pixlzr, error::PixlzrError};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, RgbImage};
+
+	/// A frame of 40 x 24 pixels, whose block at (1, 0) changes by `step`
+	#[allow(dead_code)]
+	fn frame(step: u8) -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(40, 24, |x, y| {
+			let shift = if (16..32).contains(&x) && y < 16 {
+				step
+			} else {
+				0
+			};
+			image::Rgb([
+				(x * 6) as u8 + shift,
+				(y * 10) as u8,
+				((x + y) * 3) as u8,
+			])
+		}))
+	}
+
+	#[allow(dead_code)]
+	fn sequence(steps: &[u8]) -> PixlzrSequence {
+		let mut sequence = PixlzrSequence::new(40, 24, 16, 16);
+		for (i, &step) in steps.iter().enumerate() {
+			sequence
+				.push_image(&frame(step), (40 + i as u32, 1))
+				.unwrap();
+		}
+		sequence
+	}
+
+	#[test]
+	fn test_sequence_round_trip() {
+		let sequence = sequence(&[0, 0, 50]);
+		let data = sequence.encode_to_vec().unwrap();
+		let single = sequence.frames[0].image.encode_to_vec().unwrap();
+		// The unchanged blocks are references
+		assert!(data.len() < 2 * single.len());
+
+		let decoded = PixlzrSequence::decode_from_slice(&data).unwrap();
+		assert_eq!(decoded.len(), 3);
+		for (a, b) in decoded.iter().zip(sequence.iter()) {
+			assert_eq!(a.delay, b.delay);
+			assert!(a
+				.image
+				.blocks
+				.iter()
+				.zip(b.image.blocks.iter())
+				.all(|(a, b)| a.as_slice() == b.as_slice()));
+		}
+	}
+
+	#[test]
+	fn test_sequence_threshold() {
+		let mut sequence = sequence(&[0, 1, 2, 3]);
+		let exact = sequence.encode_to_vec().unwrap();
+		sequence.threshold = 0.01;
+		let data = sequence.encode_to_vec().unwrap();
+		assert!(data.len() < exact.len());
+
+		// The small changes are dropped, against the first frame
+		let frames: Vec<_> = SequenceReader::new(data.as_slice())
+			.unwrap()
+			.collect::<Result<_, _>>()
+			.unwrap();
+		assert_eq!(frames.len(), 4);
+		for frame in frames[1..].iter() {
+			assert_eq!(
+				frame.image.blocks[1].as_slice(),
+				frames[0].image.blocks[1].as_slice()
+			);
+		}
+	}
+
+	#[test]
+	fn test_sequence_writer() {
+		let mut writer = SequenceWriter::new(
+			Vec::new(),
+			(40, 24),
+			(16, 16),
+			&Default::default(),
+		)
+		.unwrap();
+		for step in [0, 0, 10] {
+			let image = Pixlzr::from_image(&frame(step), 16, 16);
+			writer.write_frame(&image, (100, 1)).unwrap();
+		}
+		let data = writer.finish().unwrap();
+		let frames = SequenceReader::new(data.as_slice()).unwrap();
+		assert_eq!(frames.dimensions(), (40, 24));
+		assert_eq!(frames.count(), 3);
+
+		// Without its end, the sequence is truncated
+		let mut frames =
+			SequenceReader::new(&data[..data.len() - 1]).unwrap();
+		assert!(frames.nth(2).unwrap().is_ok());
+		assert!(matches!(
+			frames.next(),
+			Some(Err(PixlzrError::Truncated { .. }))
+		));
+		assert!(frames.next().is_none());
+	}
+
+	#[test]
+	fn test_sequence_frame_mismatch() {
+		let mut sequence = sequence(&[0]);
+		let other = Pixlzr::from_image(&frame(0), 8, 8);
+		assert!(matches!(
+			sequence.push_frame(other, (1, 1)),
+			Err(PixlzrError::FrameMismatch { index: 1 })
+		));
+	}
+}
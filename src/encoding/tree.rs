@@ -0,0 +1,212 @@
+use super::{
+	bytes::{ByteReader, Reader, Writer},
+	decode_compact_block, encode_block, Header,
+};
+use crate::{
+	data_types::{BlockRect, PixlzrBlock},
+	error::{PixlzrError, Result},
+};
+
+use std::iter::Peekable;
+
+/// The node of a quadtree that holds a block
+const TREE_LEAF: u8 = 0;
+/// The node of a quadtree that is split in four quadrants
+const TREE_SPLIT: u8 = 1;
+
+/// Encodes the quadtree over the `cell`, taking its blocks from `leaves`,
+/// as each block's index, the block and its rectangle, up to the index
+/// `end`.
+///
+/// Fails with `PixlzrError::LayoutMismatch` if the next blocks don't form
+/// a quadtree over the cell.
+pub(crate) fn encode_node<'a, I>(
+	cell: BlockRect,
+	leaves: &mut Peekable<I>,
+	end: usize,
+	output: &mut Writer,
+) -> Result<()>
+where
+	I: Iterator<Item = (usize, &'a PixlzrBlock, BlockRect)>,
+{
+	let Some(&(index, block, rect)) = leaves.peek() else {
+		return Err(PixlzrError::LayoutMismatch { index: end });
+	};
+	if rect == cell {
+		leaves.next();
+		output.write_u8(TREE_LEAF);
+		return encode_block(block, cell.dimensions(), false, output);
+	}
+
+	let inside = rect.x >= cell.x
+		&& rect.y >= cell.y
+		&& rect.x + rect.width <= cell.x + cell.width
+		&& rect.y + rect.height <= cell.y + cell.height;
+	match cell.quadrants() {
+		Some(quadrants) if inside => {
+			output.write_u8(TREE_SPLIT);
+			for quadrant in quadrants {
+				encode_node(quadrant, leaves, end, output)?;
+			}
+			Ok(())
+		}
+		_ => Err(PixlzrError::LayoutMismatch { index }),
+	}
+}
+
+/// Decodes the quadtree over the `cell` at (`col`, `row`) of the grid,
+/// appending its blocks and their rectangles to `line`
+pub(crate) fn decode_node(
+	view: &mut Reader,
+	header: &Header,
+	cell: BlockRect,
+	position: (usize, usize),
+	line: &mut Vec<(PixlzrBlock, BlockRect)>,
+) -> Result<()> {
+	let offset = view.position();
+	match view.read_u8()? {
+		TREE_LEAF => {
+			let block = decode_compact_block(
+				view,
+				header,
+				position,
+				cell.dimensions(),
+			)?;
+			line.push((block.into(), cell));
+			Ok(())
+		}
+		TREE_SPLIT => {
+			let quadrants =
+				cell.quadrants().ok_or(PixlzrError::Invalid {
+					offset,
+					reason: "the block is too small to be split",
+				})?;
+			for quadrant in quadrants {
+				decode_node(view, header, quadrant, position, line)?;
+			}
+			Ok(())
+		}
+		_ => Err(PixlzrError::Invalid {
+			offset,
+			reason: "unknown quadtree node",
+		}),
+	}
+}
+
+pub mod tests_on_tree {
+	#[allow(unused_imports)]
+	use super::PixlzrError;
+	#[allow(unused_imports)]
+	use crate::data_types::test_images::noise;
+	#[allow(unused_imports)]
+	use crate::data_types::{BlockLayout, BlockRect, FilterType, Pixlzr};
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use image::{DynamicImage, GenericImageView, RgbImage};
+
+	/// A flat left half, and a noisy right half
+	#[allow(dead_code)]
+	fn sample_tree() -> Pixlzr {
+		let img = RgbImage::from_fn(48, 32, |x, y| {
+			if x < 16 {
+				image::Rgb([90, 120, 150])
+			} else {
+				let noise = noise(x, y, 251);
+				image::Rgb([noise as u8, (noise * 3) as u8, 40])
+			}
+		});
+		Pixlzr::from_image_tree(
+			&DynamicImage::ImageRgb8(img),
+			0.01,
+			(32, 32),
+			(4, 4),
+		)
+		.unwrap()
+	}
+
+	#[test]
+	fn test_tree_layout() {
+		let pix = sample_tree();
+		let rects = pix.block_rects();
+		assert_eq!(rects.len(), pix.blocks.len());
+		// The flat quarter is a single block, and the noisy ones are split
+		// down to the minimum, even on the narrower trailing cell
+		assert_eq!(rects[0], BlockRect::new(0, 0, 16, 16));
+		assert!(rects.contains(&BlockRect::new(28, 28, 4, 4)));
+		assert!(rects.contains(&BlockRect::new(32, 0, 4, 8)));
+		let area: u32 = rects.iter().map(|r| r.width * r.height).sum();
+		assert_eq!(area, 48 * 32);
+	}
+
+	#[test]
+	fn test_tree_round_trip() {
+		let mut pix = sample_tree();
+		pix.shrink_by(FilterType::Nearest, 1., &PowerOfTwo);
+		let data = pix.encode_to_vec().unwrap();
+
+		let decoded = Pixlzr::decode_from_slice(&data).unwrap();
+		assert_eq!(decoded.layout, pix.layout);
+		assert!(decoded
+			.blocks
+			.iter()
+			.zip(pix.blocks.iter())
+			.all(|(a, b)| a.as_slice() == b.as_slice()));
+		let streamed = Pixlzr::read_from(data.as_slice()).unwrap();
+		assert_eq!(streamed.layout, pix.layout);
+
+		let img = decoded.to_image(FilterType::Nearest);
+		assert_eq!(img.dimensions(), (48, 32));
+		assert_eq!(img.get_pixel(3, 30).0, [90, 120, 150, 255]);
+
+		// A region keeps the quadtrees of its cells
+		let region = Pixlzr::decode_region(&data, 40, 0, 8, 8).unwrap();
+		assert_eq!(region.dimensions(), (16, 32));
+		assert!(region
+			.block_rects()
+			.iter()
+			.all(|rect| rect.x + rect.width <= 16));
+	}
+
+	#[test]
+	fn test_tree_tiny_blocks() {
+		// Blocks down to a single pixel, too small for the Sobel kernels
+		let img = RgbImage::from_fn(8, 8, |x, y| {
+			image::Rgb([noise(x, y, 251) as u8, 30, 60])
+		});
+		let mut pix = Pixlzr::from_image_tree(
+			&DynamicImage::ImageRgb8(img),
+			0.,
+			(8, 8),
+			(1, 1),
+		)
+		.unwrap();
+		assert!(pix.blocks.iter().any(|b| b.dimensions() == (1, 1)));
+		pix.shrink_directionally(FilterType::Nearest, 1., &PowerOfTwo);
+	}
+
+	#[test]
+	fn test_tree_cell_sizes() {
+		let img = DynamicImage::ImageRgb8(RgbImage::new(8, 8));
+		assert!(matches!(
+			Pixlzr::from_image_tree(&img, 0., (0, 8), (1, 1)),
+			Err(PixlzrError::Invalid { .. })
+		));
+		// Blocks can't be split below the largest minimum
+		let pix = Pixlzr::from_image_tree(&img, 0., (8, 8), (u32::MAX, 1))
+			.unwrap();
+		assert_eq!(pix.blocks.len(), 1);
+	}
+
+	#[test]
+	fn test_tree_layout_mismatch() {
+		let mut pix = sample_tree();
+		if let BlockLayout::Tree(rects) = &mut pix.layout {
+			rects[0].width = 15;
+		}
+		assert!(matches!(
+			pix.encode_to_vec(),
+			Err(PixlzrError::LayoutMismatch { index: 0 })
+		));
+	}
+}
@@ -0,0 +1,152 @@
+use crate::data_types::Semver;
+#[cfg(feature = "image-rs")]
+use image::ImageError;
+use qoi::Error as QOIError;
+use std::{
+	error::Error,
+	fmt::{Display, Formatter, Result as FMTResult},
+	io::Error as IOError,
+};
+
+/// Errors returned while reading or writting a PIXLZR image.
+#[derive(Debug)]
+pub enum PixlzrError {
+	/// An I/O error, from reading or writting a file
+	IO(IOError),
+	/// An error from the QOI encoder / decoder of a block
+	Qoi(QOIError),
+	#[cfg(feature = "image-rs")]
+	/// An error from decoding or encoding an image of another format
+	Image(ImageError),
+	/// The magic numbers (of the image or of a block) didn't match,
+	/// at the given `offset`
+	BadMagic { offset: usize },
+	/// The image was encoded with a version newer than this crate supports
+	UnsupportedVersion(Semver),
+	/// The data ended before the value at `offset` could be read
+	Truncated { offset: usize },
+	/// The sizes in the LINE_LENGTHS table don't match the data
+	LineLengthMismatch { expected: usize, found: usize },
+	/// A value at `offset` is not valid, for the given `reason`
+	Invalid { offset: usize, reason: &'static str },
+	/// The data from `start` to `end` doesn't match its checksum
+	ChecksumMismatch { start: usize, end: usize },
+	/// The frame at `index` doesn't match the dimensions or block grid of
+	/// its sequence
+	FrameMismatch { index: usize },
+	/// The blocks, from the one at `index`, don't cover the image as their
+	/// layout says
+	LayoutMismatch { index: usize },
+	/// An image doesn't have the dimensions of the one it's compared to
+	DimensionsMismatch {
+		expected: (u32, u32),
+		found: (u32, u32),
+	},
+	/// The factors given per block don't match the block grid
+	FactorsMismatch { expected: (u32, u32), found: usize },
+	/// The requested region doesn't intersect the image
+	RegionOutOfBounds {
+		x: u32,
+		y: u32,
+		width: u32,
+		height: u32,
+	},
+}
+
+pub type Result<T, E = PixlzrError> = core::result::Result<T, E>;
+
+impl PixlzrError {
+	pub fn is_io(&self) -> bool {
+		matches!(self, PixlzrError::IO(_))
+	}
+	pub fn is_qoi(&self) -> bool {
+		matches!(self, PixlzrError::Qoi(_))
+	}
+}
+
+impl From<QOIError> for PixlzrError {
+	fn from(value: QOIError) -> Self {
+		Self::Qoi(value)
+	}
+}
+#[cfg(feature = "image-rs")]
+impl From<ImageError> for PixlzrError {
+	fn from(value: ImageError) -> Self {
+		Self::Image(value)
+	}
+}
+impl From<IOError> for PixlzrError {
+	fn from(value: IOError) -> Self {
+		Self::IO(value)
+	}
+}
+
+impl Display for PixlzrError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> FMTResult {
+		match self {
+			PixlzrError::IO(err) => write!(f, "I/O error: {err}"),
+			PixlzrError::Qoi(err) => write!(f, "QOI error: {err}"),
+			#[cfg(feature = "image-rs")]
+			PixlzrError::Image(err) => write!(f, "Image error: {err}"),
+			PixlzrError::BadMagic { offset } => {
+				write!(f, "Invalid magic numbers at offset {offset}")
+			}
+			PixlzrError::UnsupportedVersion(version) => {
+				write!(f, "Unsupported PIXLZR version {version}")
+			}
+			PixlzrError::Truncated { offset } => {
+				write!(f, "The data ended too soon, at offset {offset}")
+			}
+			PixlzrError::LineLengthMismatch { expected, found } => write!(
+				f,
+				"The line lengths sum up to {expected} octets, but {found} were found"
+			),
+			PixlzrError::Invalid { offset, reason } => {
+				write!(f, "Invalid data at offset {offset}: {reason}")
+			}
+			PixlzrError::ChecksumMismatch { start, end } => write!(
+				f,
+				"The data from offset {start} to {end} is damaged, as it doesn't match its checksum"
+			),
+			PixlzrError::FrameMismatch { index } => write!(
+				f,
+				"The frame {index} doesn't match the block grid of its sequence"
+			),
+			PixlzrError::LayoutMismatch { index } => write!(
+				f,
+				"The block {index} doesn't fit the layout of the image"
+			),
+			PixlzrError::DimensionsMismatch { expected, found } => write!(
+				f,
+				"The image is {}x{}, but {}x{} was expected",
+				found.0, found.1, expected.0, expected.1
+			),
+			PixlzrError::FactorsMismatch { expected, found } => write!(
+				f,
+				"{found} factors were given, but the block grid is {}x{}",
+				expected.0, expected.1
+			),
+			PixlzrError::RegionOutOfBounds {
+				x,
+				y,
+				width,
+				height,
+			} => write!(
+				f,
+				"The region {width}x{height} at ({x}, {y}) is outside of the image"
+			),
+		}
+	}
+}
+
+impl Error for PixlzrError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			PixlzrError::IO(err) => Some(err),
+			PixlzrError::Qoi(err) => Some(err),
+			#[cfg(feature = "image-rs")]
+			PixlzrError::Image(err) => Some(err),
+			_ => None,
+		}
+	}
+}
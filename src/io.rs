@@ -1,81 +1,14 @@
-use crate::data_types::Pixlzr;
-use anyhow::Result;
-use image::EncodableLayout;
-// use qoi::Error as QOIError;
+use crate::{
+	data_types::{Pixlzr, PixlzrSequence, Preview},
+	encoding::SequenceReader,
+	error::Result,
+};
 use std::{
-	// error::Error,
-	// fmt::{Display, Error as FMTError, Formatter},
-	fs,
-	// io::Error as IOError,
+	fs::{self, File},
+	io::{BufReader, BufWriter},
 	path::Path,
 };
 
-// #[derive(Debug)]
-// pub enum PixlzrError {
-//     IO(IOError),
-//     QOI(QOIError),
-// }
-// impl PixlzrError {
-//     pub fn is_io(&self) -> bool {
-//         match self {
-//             PixlzrError::IO(_) => true,
-//             _ => false,
-//         }
-//     }
-//     pub fn is_qoi(&self) -> bool {
-//         match self {
-//             PixlzrError::QOI(_) => true,
-//             _ => false,
-//         }
-//     }
-//     pub fn unwrap_io(&self) -> &IOError {
-//         if let PixlzrError::IO(io) = self {
-//             io
-//         } else {
-//             panic!()
-//         }
-//     }
-//     pub fn unwrap_qoi(&self) -> &QOIError {
-//         if let PixlzrError::QOI(qoi) = self {
-//             qoi
-//         } else {
-//             panic!()
-//         }
-//     }
-// }
-
-// impl From<QOIError> for PixlzrError {
-//     fn from(value: QOIError) -> Self {
-//         Self::QOI(value)
-//     }
-// }
-// impl From<IOError> for PixlzrError {
-//     fn from(value: IOError) -> Self {
-//         Self::IO(value)
-//     }
-// }
-// impl Display for PixlzrError {
-//     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FMTError> {
-//         if self.is_io() {
-//             write!(f, "{:?}", self.unwrap_io())
-//         } else {
-//             write!(f, "{:?}", self.unwrap_qoi())
-//         }
-//     }
-// }
-// impl Error for PixlzrError {
-//     fn description(&self) -> &str {
-//         self.source().unwrap().description()
-//     }
-//     fn source(&self) -> Option<&(dyn Error + 'static)> {
-//         if self.is_io() {
-//             Some(self.unwrap_io())
-//         } else {
-//             Some(self.unwrap_qoi())
-//         }
-//     }
-// }
-
 impl Pixlzr {
 	pub fn open<P>(path: P) -> Result<Pixlzr>
 	where
@@ -85,12 +18,63 @@ impl Pixlzr {
 		let pix = Pixlzr::decode_from_vec(data)?;
 		Ok(pix)
 	}
+	/// Opens the file at `path`, and decodes only the blocks that intersect
+	/// the rectangle of size `width` x `height` at (`x`, `y`).
+	///
+	/// See `Pixlzr::decode_region`.
+	pub fn open_region<P>(
+		path: P,
+		x: u32,
+		y: u32,
+		width: u32,
+		height: u32,
+	) -> Result<Pixlzr>
+	where
+		P: AsRef<Path>,
+	{
+		let data = fs::read(path)?;
+		Pixlzr::decode_region(&data, x, y, width, height)
+	}
+	/// Reads only the preview embedded in the file at `path`, if any.
+	///
+	/// See `Pixlzr::read_preview_from`.
+	pub fn read_preview<P>(path: P) -> Result<Option<Preview>>
+	where
+		P: AsRef<Path>,
+	{
+		Pixlzr::read_preview_from(BufReader::new(File::open(path)?))
+	}
+	pub fn save<P>(&self, path: P) -> Result<()>
+	where
+		P: AsRef<Path>,
+	{
+		self.write_to(BufWriter::new(File::create(path)?))
+	}
+}
+
+impl PixlzrSequence {
+	pub fn open<P>(path: P) -> Result<PixlzrSequence>
+	where
+		P: AsRef<Path>,
+	{
+		let data = fs::read(path)?;
+		PixlzrSequence::decode_from_slice(&data)
+	}
+	/// Opens the file at `path`, to read and decode one frame at a time.
+	///
+	/// See `SequenceReader`.
+	pub fn open_frames<P>(
+		path: P,
+	) -> Result<SequenceReader<BufReader<File>>>
+	where
+		P: AsRef<Path>,
+	{
+		SequenceReader::new(BufReader::new(File::open(path)?))
+	}
 	pub fn save<P>(&self, path: P) -> Result<()>
 	where
 		P: AsRef<Path>,
 	{
-		let data = self.encode_to_vec()?;
-		fs::write(path, data.as_bytes())?;
-		Ok(())
+		self.write_to(BufWriter::new(File::create(path)?))
 	}
 }
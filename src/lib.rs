@@ -1,14 +1,27 @@
+pub mod analyzer;
 pub mod constants;
 mod data_types;
 pub mod encoding;
+pub mod error;
 mod io;
+#[cfg(feature = "image-rs")]
+pub mod metrics;
 pub mod operations;
+pub mod policy;
 #[cfg(feature = "image-rs")]
 mod process;
 mod split;
 // pub mod tests;
 
-pub use crate::{constants::*, data_types::*, process::*};
+pub use crate::{
+	analyzer::BlockAnalyzer,
+	constants::*,
+	data_types::*,
+	encoding::{SequenceReader, SequenceWriter},
+	error::PixlzrError,
+	policy::ShrinkPolicy,
+	process::*,
+};
 
 #[cfg(feature = "image-rs")]
 pub use image;
@@ -0,0 +1,462 @@
+#![allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+
+//! Quality metrics of an image against its source: PSNR, SSIM and MS-SSIM.
+//!
+//! The images are compared in the Oklab color space, as the detail of the
+//! blocks is measured in `operations`. The PSNR takes the `L`, `a`, `b`
+//! and alpha channels, and the SSIMs the perceived lightness `L`.
+
+use crate::{
+	data_types::{BlockRect, FilterType, Pixlzr},
+	error::{PixlzrError, Result},
+};
+
+use image::{DynamicImage, GenericImageView};
+use palette::{IntoColor, Oklab, Srgb};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+/// The side of the windows of the SSIM
+const SSIM_WINDOW: u32 = 8;
+/// The distance between SSIM windows
+const SSIM_STEP: u32 = 4;
+/// Stabilizes the luminance term of the SSIM, for a dynamic range of 1
+const SSIM_C1: f64 = 0.01 * 0.01;
+/// Stabilizes the contrast term of the SSIM, for a dynamic range of 1
+const SSIM_C2: f64 = 0.03 * 0.03;
+/// The weight of each scale of the MS-SSIM, from the finest one
+const MS_SSIM_WEIGHTS: [f64; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// The distortion of an image against its source
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quality {
+	/// The peak signal-to-noise ratio, in dB. Infinite for identical images
+	pub psnr: f32,
+	/// The structural similarity, `1` for identical images
+	pub ssim: f32,
+	/// The multi-scale structural similarity, `1` for identical images
+	pub ms_ssim: f32,
+}
+
+/// The distortion of a rendered `Pixlzr` against its source
+#[derive(Clone, Debug, PartialEq)]
+pub struct QualityReport {
+	/// The distortion of the whole image
+	pub global: Quality,
+	/// The distortion over each block, in the order of `Pixlzr::blocks`
+	pub blocks: Vec<Quality>,
+}
+
+/// An image in Oklab, with one `[L, a, b, alpha]` value per pixel
+struct Plane {
+	width: u32,
+	height: u32,
+	pixels: Vec<[f32; 4]>,
+}
+
+impl Plane {
+	fn from_image(image: &DynamicImage) -> Self {
+		let pixels = image
+			.to_rgba32f()
+			.pixels()
+			.map(|pixel| {
+				let [r, g, b, a] = pixel.0;
+				let color: Oklab<f32> =
+					Srgb::new(r, g, b).into_linear().into_color();
+				[color.l, color.a, color.b, a]
+			})
+			.collect();
+		Self {
+			width: image.width(),
+			height: image.height(),
+			pixels,
+		}
+	}
+
+	/// The lightness of the pixels in `rect`, line by line
+	fn lightness(&self, rect: BlockRect) -> Lightness {
+		let samples = (rect.y..rect.y + rect.height)
+			.flat_map(|y| {
+				let start = (y * self.width + rect.x) as usize;
+				self.pixels[start..start + rect.width as usize]
+					.iter()
+					.map(|pixel| pixel[0])
+			})
+			.collect();
+		Lightness {
+			width: rect.width,
+			height: rect.height,
+			samples,
+		}
+	}
+
+	fn rect(&self) -> BlockRect {
+		BlockRect::new(0, 0, self.width, self.height)
+	}
+}
+
+/// The lightness of a rectangle of a `Plane`
+struct Lightness {
+	width: u32,
+	height: u32,
+	samples: Vec<f32>,
+}
+
+impl Lightness {
+	/// Halves the dimensions, averaging each 2 x 2 square of samples
+	fn halve(&self) -> Self {
+		let (width, height) = (self.width / 2, self.height / 2);
+		let at =
+			|x: u32, y: u32| self.samples[(y * self.width + x) as usize];
+		let samples = (0..height)
+			.flat_map(|y| {
+				(0..width).map(move |x| {
+					let (x, y) = (2 * x, 2 * y);
+					let square =
+						[(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)];
+					square.iter().map(|&(x, y)| at(x, y)).sum::<f32>() / 4.
+				})
+			})
+			.collect();
+		Self {
+			width,
+			height,
+			samples,
+		}
+	}
+}
+
+/// Checks that `image` has the dimensions of its `source`
+fn check_dimensions(
+	source: &DynamicImage,
+	image: &DynamicImage,
+) -> Result<()> {
+	let (expected, found) = (source.dimensions(), image.dimensions());
+	if expected != found {
+		return Err(PixlzrError::DimensionsMismatch { expected, found });
+	}
+	Ok(())
+}
+
+/// The PSNR, in dB, of the pixels of `image` in `rect` against `source`
+fn plane_psnr(source: &Plane, image: &Plane, rect: BlockRect) -> f32 {
+	let mut sum = 0f64;
+	for y in rect.y..rect.y + rect.height {
+		let start = (y * source.width + rect.x) as usize;
+		let end = start + rect.width as usize;
+		for (a, b) in source.pixels[start..end]
+			.iter()
+			.zip(&image.pixels[start..end])
+		{
+			sum += a
+				.iter()
+				.zip(b)
+				.map(|(a, b)| ((a - b) as f64).powi(2))
+				.sum::<f64>();
+		}
+	}
+	let count = (rect.width as f64 * rect.height as f64 * 4.).max(1.);
+	let mse = sum / count;
+	if mse == 0. {
+		return f32::INFINITY;
+	}
+	(-10. * mse.log10()) as f32
+}
+
+/// The mean over the SSIM windows of the luminance and the
+/// contrast-structure terms
+fn ssim_terms(source: &Lightness, image: &Lightness) -> (f64, f64) {
+	let (width, height) = (source.width, source.height);
+	let window = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+	let starts = |size: u32, window: u32| {
+		(0..=size - window).step_by(SSIM_STEP as usize)
+	};
+
+	let (mut luminance, mut contrast, mut count) = (0f64, 0f64, 0f64);
+	for y in starts(height, window.1) {
+		for x in starts(width, window.0) {
+			let (mut sa, mut sb, mut saa, mut sbb, mut sab) =
+				(0f64, 0f64, 0f64, 0f64, 0f64);
+			for wy in y..y + window.1 {
+				let start = (wy * width + x) as usize;
+				let end = start + window.0 as usize;
+				for (&a, &b) in source.samples[start..end]
+					.iter()
+					.zip(&image.samples[start..end])
+				{
+					let (a, b) = (a as f64, b as f64);
+					sa += a;
+					sb += b;
+					saa += a * a;
+					sbb += b * b;
+					sab += a * b;
+				}
+			}
+			let n = (window.0 * window.1) as f64;
+			let (ma, mb) = (sa / n, sb / n);
+			let va = (saa / n - ma * ma).max(0.);
+			let vb = (sbb / n - mb * mb).max(0.);
+			let cov = sab / n - ma * mb;
+
+			luminance +=
+				(2. * ma * mb + SSIM_C1) / (ma * ma + mb * mb + SSIM_C1);
+			contrast += (2. * cov + SSIM_C2) / (va + vb + SSIM_C2);
+			count += 1.;
+		}
+	}
+	(luminance / count, contrast / count)
+}
+
+/// The SSIM of the lightness of `image` against `source`
+fn lightness_ssim(source: &Lightness, image: &Lightness) -> f32 {
+	let (luminance, contrast) = ssim_terms(source, image);
+	(luminance * contrast) as f32
+}
+
+/// The MS-SSIM of the lightness of `image` against `source`.
+///
+/// Uses as many of the 5 scales as fit an SSIM window, with their weights
+/// normalized.
+fn lightness_ms_ssim(source: &Lightness, image: &Lightness) -> f32 {
+	let mut terms = vec![ssim_terms(source, image)];
+	let (mut source, mut image) = (source.halve(), image.halve());
+	while terms.len() < MS_SSIM_WEIGHTS.len()
+		&& source.width.min(source.height) >= SSIM_WINDOW
+	{
+		terms.push(ssim_terms(&source, &image));
+		(source, image) = (source.halve(), image.halve());
+	}
+
+	let weights = &MS_SSIM_WEIGHTS[..terms.len()];
+	let total: f64 = weights.iter().sum();
+	let last = terms.len() - 1;
+	terms
+		.iter()
+		.zip(weights)
+		.enumerate()
+		.map(|(scale, (&(luminance, contrast), weight))| {
+			// Only the coarsest scale takes the luminance into account
+			let term = if scale == last {
+				luminance * contrast
+			} else {
+				contrast
+			};
+			term.max(0.).powf(weight / total)
+		})
+		.product::<f64>() as f32
+}
+
+/// The distortion of the pixels of `image` in `rect` against `source`
+fn plane_quality(
+	source: &Plane,
+	image: &Plane,
+	rect: BlockRect,
+) -> Quality {
+	let (source_l, image_l) =
+		(source.lightness(rect), image.lightness(rect));
+	Quality {
+		psnr: plane_psnr(source, image, rect),
+		ssim: lightness_ssim(&source_l, &image_l),
+		ms_ssim: lightness_ms_ssim(&source_l, &image_l),
+	}
+}
+
+/// The PSNR, in dB, of `image` against its `source`.
+///
+/// Fails with `PixlzrError::DimensionsMismatch` if their dimensions differ.
+pub fn psnr(source: &DynamicImage, image: &DynamicImage) -> Result<f32> {
+	check_dimensions(source, image)?;
+	let (source, image) =
+		(Plane::from_image(source), Plane::from_image(image));
+	Ok(plane_psnr(&source, &image, source.rect()))
+}
+
+/// The SSIM of `image` against its `source`.
+///
+/// Fails with `PixlzrError::DimensionsMismatch` if their dimensions differ.
+pub fn ssim(source: &DynamicImage, image: &DynamicImage) -> Result<f32> {
+	check_dimensions(source, image)?;
+	let (source, image) =
+		(Plane::from_image(source), Plane::from_image(image));
+	let rect = source.rect();
+	Ok(lightness_ssim(
+		&source.lightness(rect),
+		&image.lightness(rect),
+	))
+}
+
+/// The MS-SSIM of `image` against its `source`.
+///
+/// Fails with `PixlzrError::DimensionsMismatch` if their dimensions differ.
+pub fn ms_ssim(
+	source: &DynamicImage,
+	image: &DynamicImage,
+) -> Result<f32> {
+	check_dimensions(source, image)?;
+	let (source, image) =
+		(Plane::from_image(source), Plane::from_image(image));
+	let rect = source.rect();
+	Ok(lightness_ms_ssim(
+		&source.lightness(rect),
+		&image.lightness(rect),
+	))
+}
+
+/// The PSNR, SSIM and MS-SSIM of `image` against its `source`.
+///
+/// Fails with `PixlzrError::DimensionsMismatch` if their dimensions differ.
+pub fn compare(
+	source: &DynamicImage,
+	image: &DynamicImage,
+) -> Result<Quality> {
+	check_dimensions(source, image)?;
+	let (source, image) =
+		(Plane::from_image(source), Plane::from_image(image));
+	Ok(plane_quality(&source, &image, source.rect()))
+}
+
+/// The distortion of `image` against its `source`, over the whole image
+/// and over each of the `rects`.
+///
+/// Fails with `PixlzrError::DimensionsMismatch` if their dimensions differ,
+/// and with `PixlzrError::RegionOutOfBounds` if a rectangle isn't inside
+/// the image.
+pub fn compare_blocks(
+	source: &DynamicImage,
+	image: &DynamicImage,
+	rects: &[BlockRect],
+) -> Result<QualityReport> {
+	check_dimensions(source, image)?;
+	let (width, height) = source.dimensions();
+	if let Some(rect) = rects.iter().find(|rect| {
+		rect.width == 0
+			|| rect.height == 0
+			|| rect.x.checked_add(rect.width).is_none_or(|x1| x1 > width)
+			|| rect.y.checked_add(rect.height).is_none_or(|y1| y1 > height)
+	}) {
+		return Err(PixlzrError::RegionOutOfBounds {
+			x: rect.x,
+			y: rect.y,
+			width: rect.width,
+			height: rect.height,
+		});
+	}
+
+	let (source, image) =
+		(Plane::from_image(source), Plane::from_image(image));
+	Ok(QualityReport {
+		global: plane_quality(&source, &image, source.rect()),
+		blocks: rects
+			.par_iter()
+			.map(|&rect| plane_quality(&source, &image, rect))
+			.collect(),
+	})
+}
+
+impl Pixlzr {
+	/// Renders the image with `filter`, as `to_image` does, and measures
+	/// its distortion against its `source`, over the whole image and over
+	/// each block.
+	///
+	/// Fails with `PixlzrError::DimensionsMismatch` if their dimensions
+	/// differ.
+	pub fn quality(
+		&self,
+		source: &DynamicImage,
+		filter: FilterType,
+	) -> Result<QualityReport> {
+		compare_blocks(source, &self.to_image(filter), &self.block_rects())
+	}
+}
+
+pub mod tests_on_metrics {
+	#[allow(unused_imports)]
+	use super::{compare, compare_blocks, ms_ssim, psnr, ssim};
+	#[allow(unused_imports)]
+	use crate::data_types::test_images::noise;
+	#[allow(unused_imports)]
+	use crate::policy::PowerOfTwo;
+	#[allow(unused_imports)]
+	use crate::{
+		data_types::{BlockRect, FilterType, Pixlzr},
+		error::PixlzrError,
+	};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, RgbImage};
+
+	#[allow(dead_code)]
+	fn sample_image() -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(64, 48, |x, y| {
+			let noise = noise(x, y, 61);
+			image::Rgb([
+				(x * 3 + noise) as u8,
+				(y * 5) as u8,
+				((x + y) * 2) as u8,
+			])
+		}))
+	}
+
+	#[test]
+	fn test_identical_images() {
+		let img = sample_image();
+		let quality = compare(&img, &img).unwrap();
+		assert_eq!(quality.psnr, f32::INFINITY);
+		assert!((quality.ssim - 1.).abs() < 1e-6);
+		assert!((quality.ms_ssim - 1.).abs() < 1e-6);
+		assert!(matches!(
+			psnr(&img, &img.crop_imm(0, 0, 8, 8)),
+			Err(PixlzrError::DimensionsMismatch { .. })
+		));
+	}
+
+	#[test]
+	fn test_distortion_lowers_quality() {
+		let img = sample_image();
+		let blurred = img.blur(1.5);
+		let softened = img.blur(0.5);
+		let (a, b) = (
+			psnr(&img, &blurred).unwrap(),
+			psnr(&img, &softened).unwrap(),
+		);
+		assert!(a.is_finite() && a < b);
+		assert!(
+			ssim(&img, &blurred).unwrap() < ssim(&img, &softened).unwrap()
+		);
+		let ms = ms_ssim(&img, &blurred).unwrap();
+		assert!(ms > 0. && ms < 1.);
+	}
+
+	#[test]
+	fn test_pixlzr_quality() {
+		let img = sample_image();
+		let mut pix = Pixlzr::from_image(&img, 16, 16);
+		// Unshrunk blocks render the source back
+		let report = pix.quality(&img, FilterType::Nearest).unwrap();
+		assert!(report.global.psnr.is_infinite());
+		assert_eq!(report.blocks.len(), pix.blocks.len());
+
+		pix.shrink_by(FilterType::Lanczos3, 1., &PowerOfTwo);
+		let report = pix.quality(&img, FilterType::Lanczos3).unwrap();
+		assert!(report.global.psnr.is_finite());
+		// Each block is measured over its own rectangle
+		let rects = pix.block_rects();
+		let last = compare_blocks(
+			&img,
+			&pix.to_image(FilterType::Lanczos3),
+			&rects[rects.len() - 1..],
+		)
+		.unwrap();
+		assert_eq!(report.blocks.last(), last.blocks.first());
+
+		for rect in [
+			BlockRect::new(60, 0, 8, 8),
+			// Overflowing its right and bottom sides
+			BlockRect::new(8, 0, u32::MAX, 8),
+			BlockRect::new(0, 8, 8, u32::MAX),
+		] {
+			assert!(matches!(
+				compare_blocks(&img, &img, &[rect]),
+				Err(PixlzrError::RegionOutOfBounds { .. })
+			));
+		}
+	}
+}
@@ -14,10 +14,50 @@
 ///   (img: &PixlzrBlock, ...) -> PixlzrBlock
 /// - !image-rs, !fir:
 ///   panic!
-use crate::data_types::{FilterType as P_FilterType, PixlzrBlock};
+use crate::{
+	data_types::{ColorType, FilterType as P_FilterType, PixlzrBlock},
+	policy::ShrinkPolicy,
+};
 
 use palette::{IntoColor, Oklab, Oklaba, Srgb, Srgba};
 
+/// The RGBA values, in `[0; 1]` (or beyond, for HDR), of a `pixel` of the
+/// `layout` color type
+#[inline]
+fn to_rgba(pixel: &[u8], layout: ColorType) -> [f32; 4] {
+	let mut samples = [0f32; 4];
+	let channels = layout.channels();
+	let size = layout.sample_type().size();
+	for (sample, bytes) in samples.iter_mut().zip(pixel.chunks_exact(size))
+	{
+		*sample = match *bytes {
+			[value] => value as f32 / u8::MAX as f32,
+			[a, b] => u16::from_ne_bytes([a, b]) as f32 / u16::MAX as f32,
+			[a, b, c, d] => f32::from_ne_bytes([a, b, c, d]),
+			_ => 0.,
+		};
+	}
+	match channels {
+		1 => [samples[0], samples[0], samples[0], 1.],
+		2 => [samples[0], samples[0], samples[0], samples[1]],
+		3 => [samples[0], samples[1], samples[2], 1.],
+		_ => samples,
+	}
+}
+
+/// The average RGBA values, in `[0; 1]`, of the pixels of `block`
+pub fn get_block_average(block: &PixlzrBlock) -> [f32; 4] {
+	let layout = block.color();
+	let count = (block.width() * block.height()).max(1) as f32;
+	let mut sum = [0f32; 4];
+	for pixel in block.pixels() {
+		for (total, sample) in sum.iter_mut().zip(to_rgba(pixel, layout)) {
+			*total += sample;
+		}
+	}
+	sum.map(|total| total / count)
+}
+
 /// Calculates a `[0; 1]` value for the pixel variance of a given `img` image
 ///
 /// 1. Calculates the average of pixel values
@@ -49,14 +89,14 @@ where
 	*/
 	// 1. Calculates the average of pixel values
 	let count = (block.width() * block.height()) as f32;
+	let layout = block.color();
 	if block.has_alpha() {
 		let (average, count) = {
 			let mut sum = [0.; 4];
 			for pixel in block.pixels() {
+				let [r, g, b, a] = to_rgba(pixel, layout);
 				let color: Oklaba<f32> =
-					Srgba::new(pixel[0], pixel[1], pixel[2], pixel[3])
-						.into_linear()
-						.into_color();
+					Srgba::new(r, g, b, a).into_linear().into_color();
 				sum[0] += color.a;
 				sum[1] += color.b;
 				sum[2] += color.l;
@@ -73,10 +113,9 @@ where
 		let delta = {
 			let mut delta = [0.; 4];
 			for pixel in block.pixels() {
+				let [r, g, b, a] = to_rgba(pixel, layout);
 				let color: Oklaba<f32> =
-					Srgba::new(pixel[0], pixel[1], pixel[2], pixel[3])
-						.into_linear()
-						.into_color();
+					Srgba::new(r, g, b, a).into_linear().into_color();
 				delta[0] += before(color.a, average[0]);
 				delta[1] += before(color.b, average[1]);
 				delta[2] += before(color.l, average[2]);
@@ -91,10 +130,9 @@ where
 		let (average, count) = {
 			let mut sum = [0.; 3];
 			for pixel in block.pixels() {
+				let [r, g, b, _] = to_rgba(pixel, layout);
 				let color: Oklab<f32> =
-					Srgb::new(pixel[0], pixel[1], pixel[2])
-						.into_linear()
-						.into_color();
+					Srgb::new(r, g, b).into_linear().into_color();
 				sum[0] += color.a;
 				sum[1] += color.b;
 				sum[2] += color.l;
@@ -109,10 +147,9 @@ where
 		let delta = {
 			let mut delta = [0.; 3];
 			for pixel in block.pixels() {
+				let [r, g, b, _] = to_rgba(pixel, layout);
 				let color: Oklab<f32> =
-					Srgb::new(pixel[0], pixel[1], pixel[2])
-						.into_linear()
-						.into_color();
+					Srgb::new(r, g, b).into_linear().into_color();
 				delta[0] += before(color.a, average[0]);
 				delta[1] += before(color.b, average[1]);
 				delta[2] += before(color.l, average[2]);
@@ -125,6 +162,36 @@ where
 	}
 }
 
+/// Calculates the mean absolute difference, in `[0; 1]`, of the RGBA
+/// samples of two blocks
+///
+/// Returns `None` if the blocks differ in dimensions or color type.
+pub fn get_block_difference(
+	a: &PixlzrBlock,
+	b: &PixlzrBlock,
+) -> Option<f32> {
+	if a.dimensions() != b.dimensions() || a.color() != b.color() {
+		return None;
+	}
+	if a.as_slice() == b.as_slice() {
+		return Some(0.);
+	}
+	let layout = a.color();
+	let count = (a.width() * a.height()).max(1) as f32;
+	let sum: f32 = a
+		.pixels()
+		.zip(b.pixels())
+		.map(|(pa, pb)| {
+			let (pa, pb) = (to_rgba(pa, layout), to_rgba(pb, layout));
+			pa.iter()
+				.zip(pb.iter())
+				.map(|(x, y)| (x - y).abs())
+				.sum::<f32>()
+		})
+		.sum();
+	Some(sum / (count * 4.))
+}
+
 fn parse_value(value: f32) -> f32 {
 	if value.is_sign_positive() {
 		return value;
@@ -137,21 +204,32 @@ fn parse_value(value: f32) -> f32 {
 	}
 }
 
-pub fn reduce_image_section(
+/// Shrinks the `block` along each axis, to the scale the `policy` gives to
+/// the detail `value` along it
+pub fn reduce_image_section<P>(
 	value: (f32, f32),
 	block: &PixlzrBlock,
 	filter_downscale: P_FilterType,
-) -> PixlzrBlock {
+	policy: &P,
+) -> PixlzrBlock
+where
+	P: ShrinkPolicy + ?Sized,
+{
 	let value = (parse_value(value.0), parse_value(value.1));
 	// println!("Post-value: {}", value.0);
-	let level_hz = value.0.log2().round().min(0f32).exp2();
-	let level_vr = value.1.log2().round().min(0f32).exp2();
+	let level_hz = policy.scale(value.0);
+	let level_vr = policy.scale(value.1);
 	let (width, height) = block.dimensions();
 	let width = (width as f64 * level_hz as f64).max(1f64).ceil() as u32;
 	let height = (height as f64 * level_vr as f64).max(1f64).ceil() as u32;
 	// Resizes the image down
 	let mut img = block.resize(width, height, filter_downscale);
-	img.set_block_value(value.0.hypot(value.1));
+	if value.0 == value.1 {
+		img.set_block_value(value.0.hypot(value.1));
+	} else {
+		// Keeps both values of a block shrunk along each axis separately
+		img.set_axis_values(value);
+	}
 	img
 }
 
@@ -188,7 +266,10 @@ macro_rules! add_px_ {
 /// 2. Calculates the total difference of these values
 /// 3. Normalizes the result to `[0; 1]`
 ///
+/// Blocks smaller than 3x3 pixels are flat.
+///
 /// TODO: Nowadays, it ignores an alpha channel
+#[allow(clippy::neg_multiply)]
 pub fn get_block_variance_directionally(
 	block: &PixlzrBlock,
 ) -> (f32, f32) {
@@ -210,12 +291,24 @@ pub fn get_block_variance_directionally(
 	// 1. Calculates the average of pixel values
 	let width = block.width() as usize;
 	let height = block.height() as usize;
+	// The 3x3 kernels don't fit in smaller blocks
+	if width < 3 || height < 3 {
+		return (0., 0.);
+	}
 
-	let mut sum_hz = vec![0u64; 3];
-	let mut sum_vr = vec![0u64; 3];
+	let mut sum_hz = [0u64; 3];
+	let mut sum_vr = [0u64; 3];
 
-	let pixels: Vec<[u8; 3]> =
-		block.pixels().map(|px| [px[0], px[1], px[2]]).collect();
+	// The pixels, scaled to 8 bits per sample
+	let layout = block.color();
+	let pixels: Vec<[u8; 3]> = block
+		.pixels()
+		.map(|px| {
+			let [r, g, b, _] = to_rgba(px, layout);
+			[r, g, b]
+				.map(|value| (value * 255.).round().clamp(0., 255.) as u8)
+		})
+		.collect();
 
 	for y in 0..height - 2 {
 		for x in 0..width - 2 {
@@ -257,3 +350,148 @@ pub fn get_block_variance_directionally(
 		(sum_vr.iter().sum::<u64>() as f64 / factor) as f32,
 	)
 }
+
+/// The Oklab lightness, in `[0; 1]`, of each pixel of `block`, line by line
+pub fn get_block_lightness(block: &PixlzrBlock) -> Vec<f32> {
+	let layout = block.color();
+	block
+		.pixels()
+		.map(|pixel| {
+			let [r, g, b, _] = to_rgba(pixel, layout);
+			let color: Oklab<f32> =
+				Srgb::new(r, g, b).into_linear().into_color();
+			color.l.clamp(0., 1.)
+		})
+		.collect()
+}
+
+/// Calculates a `[0; 1]` value for the standard deviation of the Laplacian
+/// of the lightness of `block`
+///
+/// The borders are extended. A checkerboard of black and white scores
+/// close to `1`, as its Laplacian swings between `-4` and `4`.
+pub fn get_block_laplacian(block: &PixlzrBlock) -> f32 {
+	let (width, height) =
+		(block.width() as usize, block.height() as usize);
+	let lightness = get_block_lightness(block);
+	let at = |x: usize, y: usize| lightness[y * width + x];
+
+	let mut laplacian = Vec::with_capacity(lightness.len());
+	for y in 0..height {
+		for x in 0..width {
+			let (left, right) =
+				(x.saturating_sub(1), (x + 1).min(width - 1));
+			let (up, down) =
+				(y.saturating_sub(1), (y + 1).min(height - 1));
+			laplacian.push(
+				4. * at(x, y)
+					- at(left, y) - at(right, y)
+					- at(x, up) - at(x, down),
+			);
+		}
+	}
+
+	let count = laplacian.len().max(1) as f32;
+	let mean = laplacian.iter().sum::<f32>() / count;
+	let variance =
+		laplacian.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / count;
+	(variance.sqrt() / 4.).min(1.)
+}
+
+/// The number of bins of the lightness histogram of `get_block_entropy`
+const ENTROPY_BINS: usize = 256;
+
+/// Calculates a `[0; 1]` value for the Shannon entropy of the histogram of
+/// the lightness of `block`
+///
+/// The entropy is normalized by its maximum, for as many bins as pixels,
+/// up to 256 bins.
+pub fn get_block_entropy(block: &PixlzrBlock) -> f32 {
+	let lightness = get_block_lightness(block);
+	let mut histogram = [0usize; ENTROPY_BINS];
+	for value in &lightness {
+		let bin = (value * (ENTROPY_BINS - 1) as f32).round() as usize;
+		histogram[bin.min(ENTROPY_BINS - 1)] += 1;
+	}
+
+	let count = lightness.len() as f32;
+	let entropy: f32 = histogram
+		.iter()
+		.filter(|&&n| n > 0)
+		.map(|&n| {
+			let p = n as f32 / count;
+			-p * p.log2()
+		})
+		.sum();
+	let max = (lightness.len().min(ENTROPY_BINS) as f32).log2();
+	if max > 0. {
+		(entropy / max).min(1.)
+	} else {
+		0.
+	}
+}
+
+/// The side of the tiles of `get_block_dct_energy`
+const DCT_SIZE: usize = 8;
+/// The least sum of the indexes of a high frequency DCT coefficient
+const DCT_HIGH_FREQUENCY: usize = 4;
+/// The AC energy of a tile that is considered noise, so that nearly flat
+/// blocks score close to `0`
+const DCT_NOISE_FLOOR: f32 = 64. * (2. / 255.) * (2. / 255.);
+
+/// Calculates a `[0; 1]` value for the share of the energy of the 8x8 DCT
+/// of the lightness of `block` that is in its high frequencies
+///
+/// Partial tiles have their borders extended. Only the AC coefficients
+/// count, and those whose indexes sum up to 4 or more are high frequency.
+pub fn get_block_dct_energy(block: &PixlzrBlock) -> f32 {
+	let (width, height) =
+		(block.width() as usize, block.height() as usize);
+	let lightness = get_block_lightness(block);
+
+	// The orthonormal DCT-II basis, by frequency and then by sample
+	let mut basis = [[0f32; DCT_SIZE]; DCT_SIZE];
+	for (u, row) in basis.iter_mut().enumerate() {
+		let scale = if u == 0 { 1. / 8f32.sqrt() } else { 0.5 };
+		for (x, value) in row.iter_mut().enumerate() {
+			*value = scale
+				* ((2 * x + 1) as f32 * u as f32 * std::f32::consts::PI
+					/ (2 * DCT_SIZE) as f32)
+					.cos();
+		}
+	}
+
+	let (mut high, mut total, mut tiles) = (0f32, 0f32, 0f32);
+	for tile_y in (0..height).step_by(DCT_SIZE) {
+		for tile_x in (0..width).step_by(DCT_SIZE) {
+			let mut tile = [[0f32; DCT_SIZE]; DCT_SIZE];
+			for (y, row) in tile.iter_mut().enumerate() {
+				let y = (tile_y + y).min(height - 1);
+				for (x, value) in row.iter_mut().enumerate() {
+					let x = (tile_x + x).min(width - 1);
+					*value = lightness[y * width + x];
+				}
+			}
+			for (v, basis_v) in basis.iter().enumerate() {
+				for (u, basis_u) in basis.iter().enumerate() {
+					if u + v == 0 {
+						continue;
+					}
+					let mut coefficient = 0.;
+					for (row, &by) in tile.iter().zip(basis_v) {
+						for (&value, &bx) in row.iter().zip(basis_u) {
+							coefficient += value * bx * by;
+						}
+					}
+					let energy = coefficient * coefficient;
+					total += energy;
+					if u + v >= DCT_HIGH_FREQUENCY {
+						high += energy;
+					}
+				}
+			}
+			tiles += 1.;
+		}
+	}
+	(high / (total + tiles * DCT_NOISE_FLOOR).max(f32::EPSILON)).min(1.)
+}
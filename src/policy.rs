@@ -0,0 +1,123 @@
+//! Mappings of the detail value of a block into the scale it's shrunk to.
+
+/// Maps the detail value of a block, along an axis, into the scale of the
+/// block along it.
+///
+/// The values are in `[0; 1]` for the usual blocks, and the scales in
+/// `(0; 1]`, `1` keeping the side whole. Closures of a value into a scale
+/// are policies too.
+pub trait ShrinkPolicy: Sync {
+	/// The scale of a side of a block of detail `value`
+	fn scale(&self, value: f32) -> f32;
+}
+
+impl<F> ShrinkPolicy for F
+where
+	F: Fn(f32) -> f32 + Sync,
+{
+	fn scale(&self, value: f32) -> f32 {
+		self(value)
+	}
+}
+
+/// Rounds the value to the nearest power of two, up to `1`: each side is
+/// kept whole or halved a number of times.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PowerOfTwo;
+
+impl ShrinkPolicy for PowerOfTwo {
+	fn scale(&self, value: f32) -> f32 {
+		value.log2().round().min(0.).exp2()
+	}
+}
+
+/// Takes the value as the scale, up to `1`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Continuous;
+
+impl ShrinkPolicy for Continuous {
+	fn scale(&self, value: f32) -> f32 {
+		value.min(1.)
+	}
+}
+
+/// A lookup table of (least value, scale) steps, in ascending order of
+/// value: the scale is the one of the last step the value reaches, or the
+/// one of the first step, for lower values.
+#[derive(Clone, Debug, Default)]
+pub struct Stepped(pub Vec<(f32, f32)>);
+
+impl ShrinkPolicy for Stepped {
+	fn scale(&self, value: f32) -> f32 {
+		let steps = &self.0;
+		let reached = steps.partition_point(|&(least, _)| least <= value);
+		steps
+			.get(reached.saturating_sub(1))
+			.map_or(1., |&(_, scale)| scale)
+	}
+}
+
+/// Clamps the scales of the `policy` between `min` and `max`, such as to
+/// never shrink a block below a quarter of its size.
+#[derive(Clone, Copy, Debug)]
+pub struct Clamped<P: ShrinkPolicy> {
+	pub policy: P,
+	pub min: f32,
+	pub max: f32,
+}
+
+impl<P: ShrinkPolicy> Clamped<P> {
+	pub fn new(policy: P, min: f32, max: f32) -> Self {
+		Self { policy, min, max }
+	}
+}
+
+impl<P: ShrinkPolicy> ShrinkPolicy for Clamped<P> {
+	fn scale(&self, value: f32) -> f32 {
+		self.policy.scale(value).clamp(self.min, self.max)
+	}
+}
+
+pub mod tests_on_policy {
+	#[allow(unused_imports)]
+	use super::{Clamped, Continuous, PowerOfTwo, ShrinkPolicy, Stepped};
+	#[allow(unused_imports)]
+	use crate::data_types::{FilterType, Pixlzr};
+	#[allow(unused_imports)]
+	use image::{DynamicImage, RgbImage};
+
+	#[test]
+	fn test_policies() {
+		assert_eq!(PowerOfTwo.scale(0.3), 0.25);
+		assert_eq!(PowerOfTwo.scale(4.), 1.);
+		assert_eq!(Continuous.scale(0.3), 0.3);
+		assert_eq!(Continuous.scale(4.), 1.);
+
+		let stepped = Stepped(vec![(0., 0.125), (0.1, 0.5), (0.5, 1.)]);
+		assert_eq!(stepped.scale(-1.), 0.125);
+		assert_eq!(stepped.scale(0.1), 0.5);
+		assert_eq!(stepped.scale(0.7), 1.);
+
+		let clamped = Clamped::new(PowerOfTwo, 0.25, 1.);
+		assert_eq!(clamped.scale(0.), 0.25);
+		assert_eq!(clamped.scale(0.6), 0.5);
+	}
+
+	#[test]
+	fn test_shrink_by_clamped() {
+		let img = RgbImage::from_fn(32, 32, |x, y| {
+			image::Rgb([(x * 8) as u8, (y * 8) as u8, 70])
+		});
+		let img = DynamicImage::ImageRgb8(img);
+
+		let mut pix = Pixlzr::from_image(&img, 16, 16);
+		pix.shrink_by(FilterType::Nearest, 0.01, &PowerOfTwo);
+		assert!(pix.blocks.iter().all(|b| b.dimensions() == (1, 1)));
+
+		// Never below a quarter of the size
+		let mut pix = Pixlzr::from_image(&img, 16, 16);
+		let policy = Clamped::new(PowerOfTwo, 0.25, 1.);
+		pix.shrink_by(FilterType::Nearest, 0.01, &policy);
+		assert!(pix.blocks.iter().all(|b| b.dimensions() == (4, 4)));
+	}
+}
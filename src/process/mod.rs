@@ -22,6 +22,7 @@ pub mod tree;
 use crate::{
 	data_types::{block::PixlzrBlockImage, FilterType as P_FilterType},
 	operations::*,
+	policy::PowerOfTwo,
 };
 
 use crate::split::split_image;
@@ -54,8 +55,13 @@ where
 		let value =
 			get_block_variance(&block, &before_average, &after_average);
 		let img = PixlzrBlockImage::from(
-			reduce_image_section((value, value), &block, filter_downscale)
-				.resize(w0, h0, filter_upscale),
+			reduce_image_section(
+				(value, value),
+				&block,
+				filter_downscale,
+				&PowerOfTwo,
+			)
+			.resize(w0, h0, filter_upscale),
 		)
 		.data;
 		// Saves it's data in the output buffer
@@ -90,8 +96,13 @@ pub fn process_custom(
 		let value =
 			get_block_variance(&block, &before_average, &after_average);
 		let img = PixlzrBlockImage::from(
-			reduce_image_section((value, value), &block, filter_downscale)
-				.resize(w0, h0, filter_upscale),
+			reduce_image_section(
+				(value, value),
+				&block,
+				filter_downscale,
+				&PowerOfTwo,
+			)
+			.resize(w0, h0, filter_upscale),
 		)
 		.data;
 		// Saves it's data in the output buffer
@@ -3,6 +3,7 @@
 use crate::{
 	data_types::{block::PixlzrBlockImage, FilterType as P_FilterType},
 	operations::*,
+	policy::PowerOfTwo,
 };
 
 use crate::split::split_image;
@@ -60,6 +61,7 @@ pub fn process_custom(
 					(value, value),
 					&block,
 					filter_downscale,
+					&PowerOfTwo,
 				)
 				.resize(w0, h0, filter_upscale),
 			)
@@ -21,6 +21,7 @@ pub fn get_image_block(
 		width,
 		height,
 		block_value: None,
+		axis_values: None,
 		data: image.crop_imm(x, y, width, height),
 	}
 	.into()